@@ -1,11 +1,199 @@
+mod avatar;
+mod create_room;
+mod edit_history;
+mod emoji_picker;
+mod encryption_setup;
+mod html;
+mod invite;
+mod media;
+mod member_list;
+mod moderation;
+mod new_dm;
+mod room_directory;
+mod room_settings;
+mod room_view;
+mod search;
+mod security_settings;
+mod sessions;
+mod space;
+mod upload;
+pub mod verification;
+
+use crate::modal::modal;
+use chrono::DateTime;
+use chrono::Local;
+use create_room::CreateRoom;
+use emoji_picker::EmojiPicker;
+use encryption_setup::EncryptionSetup;
+use iced::Alignment;
+use iced::Color;
 use iced::Element;
+use iced::Event;
+use iced::Font;
+use iced::Length;
+use iced::Subscription;
 use iced::Task;
+use iced::event;
+use iced::font;
+use iced::futures::Stream;
+use iced::futures::stream;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::rich_text;
 use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::span;
+use iced::widget::text;
+use iced::widget::text::Span;
+use iced::widget::text_input;
+use iced::widget::tooltip;
+use iced::window;
+use invite::Invite;
+use invite::InvitePreview;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk::encryption::VerificationState;
+use matrix_sdk::encryption::verification::VerificationRequest;
+use matrix_sdk::event_handler::EventHandlerDropGuard;
+use matrix_sdk::ruma::MilliSecondsSinceUnixEpoch;
+use matrix_sdk::ruma::OwnedEventId;
+use matrix_sdk::ruma::OwnedRoomId;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::RoomId;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::api::client::uiaa::AuthData;
+use matrix_sdk::ruma::api::client::uiaa::Password;
+use matrix_sdk::ruma::api::client::uiaa::UserIdentifier;
+use matrix_sdk::sleep::sleep;
+use matrix_sdk_ui::Timeline;
+use matrix_sdk_ui::timeline::RoomExt;
+use matrix_sdk_ui::timeline::TimelineEventItemId;
+use matrix_sdk_ui::timeline::TimelineFocus;
+use matrix_sdk_ui::timeline::TimelineReadReceiptTracking;
+use media::MediaCache;
+use member_list::MemberList;
+use moderation::Moderation;
+use new_dm::NewDm;
+use room_directory::RoomDirectory;
+use room_settings::RoomSettings;
+use room_view::RoomView;
+use search::SearchIndex;
+use security_settings::SecuritySettings;
+use sessions::Sessions;
+use space::SpaceTree;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use verification::Verification;
+
+const FONT_SIZE: u32 = 13;
+const ROOM_LIST_WIDTH: f32 = 250.0;
+const SPACE_BAR_WIDTH: f32 = 56.0;
+const SPACE_ICON_SIZE: f32 = 32.0;
+const THREAD_PANEL_WIDTH: f32 = 400.0;
+const MEMBER_LIST_WIDTH: f32 = 280.0;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+const SYNC_RETRY_DELAY: Duration = Duration::from_secs(5);
+// Messages loaded around a search result when jumping to it
+const SEARCH_CONTEXT_EVENTS: u16 = 20;
+const BOLD: Font = Font {
+    weight: font::Weight::Bold,
+    ..Font::DEFAULT
+};
 
-pub struct App {}
+pub struct App {
+    client: Client,
+    rooms: Vec<Room>,
+    spaces: Vec<Room>,
+    // The space whose rooms are listed, None for all rooms
+    space_tree: Option<SpaceTree>,
+    invites: Vec<Invite>,
+    // Shown instead of the room view while an invite is selected
+    invite_preview: Option<InvitePreview>,
+    room_view: Option<RoomView>,
+    thread_view: Option<RoomView>,
+    member_list: Option<MemberList>,
+    media: MediaCache,
+    emoji_picker: EmojiPicker,
+    // Which pane and message the emoji picker is open for
+    reacting_to: Option<(Pane, TimelineEventItemId)>,
+    create_room: Option<CreateRoom>,
+    room_directory: Option<RoomDirectory>,
+    new_dm: Option<NewDm>,
+    room_settings: Option<RoomSettings>,
+    moderation: Option<Moderation>,
+    // Whether this session is signed by our cross-signing identity
+    session_verification: VerificationState,
+    verification: Option<Verification>,
+    // Requests that arrived while another verification was open
+    pending_verifications: VecDeque<VerificationRequest>,
+    encryption_setup: Option<EncryptionSetup>,
+    security_settings: Option<SecuritySettings>,
+    sessions: Option<Sessions>,
+    search_index: SearchIndex,
+    search_query: String,
+    search_hits: Vec<search::Hit>,
+    // Kept apart from `error`, which every successful sync clears
+    search_error: Option<String>,
+    // Dropping the app stops indexing synced messages
+    _search_handlers: [EventHandlerDropGuard; 2],
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Pane {
+    Room,
+    Thread,
+}
 
 #[derive(Clone)]
-pub enum Message {}
+pub enum Message {
+    Synced(Result<(), String>),
+    InvitesLoaded(Vec<Invite>),
+    InviteSelected(OwnedRoomId),
+    Invite(invite::Message),
+    SpaceSelected(Option<OwnedRoomId>),
+    Space(space::Message),
+    RoomSelected(OwnedRoomId),
+    TimelineOpened(Room, Result<Arc<Timeline>, String>),
+    RoomView(room_view::Message),
+    ThreadOpened(Room, Result<Arc<Timeline>, String>),
+    ThreadView(room_view::Message),
+    CloseThread,
+    ToggleMembers,
+    MemberList(member_list::Message),
+    EmojiPicker(emoji_picker::Message),
+    NewRoom,
+    CreateRoom(create_room::Message),
+    ExploreRooms,
+    RoomDirectory(room_directory::Message),
+    NewDm,
+    StartDm(new_dm::Message),
+    OpenRoomSettings,
+    RoomSettings(room_settings::Message),
+    Moderation(moderation::Message),
+    SessionVerificationChanged(VerificationState),
+    VerifySession,
+    VerificationRequested(VerificationRequest),
+    Verification(verification::Message),
+    EncryptionSetupNeeded(bool),
+    EncryptionSetup(encryption_setup::Message),
+    OpenSecuritySettings,
+    SecuritySettings(security_settings::Message),
+    OpenSessions,
+    Sessions(sessions::Message),
+    SearchInput(String),
+    ClearSearch,
+    OpenSearchHit(OwnedRoomId, OwnedEventId),
+    SearchIndexLoaded(Result<(), String>),
+    SearchIndexSaved(Result<(), String>),
+    DismissSearchError,
+}
 
 pub enum Action {
     None,
@@ -13,11 +201,1138 @@ pub enum Action {
 }
 
 impl App {
+    pub fn new(client: Client) -> (Self, Task<Message>) {
+        let search_index = SearchIndex::new(&client);
+        let search_handlers = search::index_sync(&client, search_index.clone());
+        (
+            Self {
+                client: client.clone(),
+                rooms: Vec::new(),
+                spaces: Vec::new(),
+                space_tree: None,
+                invites: Vec::new(),
+                invite_preview: None,
+                room_view: None,
+                thread_view: None,
+                member_list: None,
+                media: MediaCache::new(&client),
+                emoji_picker: EmojiPicker::new(),
+                reacting_to: None,
+                create_room: None,
+                room_directory: None,
+                new_dm: None,
+                room_settings: None,
+                moderation: None,
+                session_verification: VerificationState::Unknown,
+                verification: None,
+                pending_verifications: VecDeque::new(),
+                encryption_setup: None,
+                security_settings: None,
+                sessions: None,
+                search_index: search_index.clone(),
+                search_query: String::new(),
+                search_hits: Vec::new(),
+                search_error: None,
+                _search_handlers: search_handlers,
+                error: None,
+            },
+            Task::batch([
+                Task::perform(search_index.load(), Message::SearchIndexLoaded),
+                Task::perform(
+                    encryption_setup::is_needed(client.clone()),
+                    Message::EncryptionSetupNeeded,
+                ),
+                Task::run(
+                    client.encryption().verification_state(),
+                    Message::SessionVerificationChanged,
+                ),
+                Task::run(
+                    verification::requests(client.clone()),
+                    Message::VerificationRequested,
+                ),
+                Task::run(sync(client), Message::Synced),
+            ]),
+        )
+    }
+
     pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Synced(result) => match result {
+                Ok(()) => {
+                    self.error = None;
+                    let save = Task::perform(
+                        self.search_index.clone().save(),
+                        Message::SearchIndexSaved,
+                    );
+                    (self.spaces, self.rooms) = self
+                        .client
+                        .joined_rooms()
+                        .into_iter()
+                        .partition(|room| room.is_space());
+                    self.spaces.sort_by_key(room_name);
+                    self.rooms.sort_by_key(room_name);
+
+                    // Only look up inviters and avatars when the set of
+                    // invites changes
+                    let mut invited: Vec<OwnedRoomId> = self
+                        .client
+                        .invited_rooms()
+                        .iter()
+                        .map(|room| room.room_id().to_owned())
+                        .collect();
+                    let mut loaded: Vec<OwnedRoomId> = self
+                        .invites
+                        .iter()
+                        .map(|invite| invite.room.room_id().to_owned())
+                        .collect();
+                    invited.sort();
+                    loaded.sort();
+                    if invited != loaded {
+                        return Action::Task(Task::batch([
+                            save,
+                            Task::perform(
+                                invite::load(
+                                    self.client.clone(),
+                                    self.media.clone(),
+                                ),
+                                Message::InvitesLoaded,
+                            ),
+                        ]));
+                    }
+                    return Action::Task(save);
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::InvitesLoaded(invites) => {
+                self.invites = invites;
+                if let Some(preview) = &self.invite_preview
+                    && !self.invites.iter().any(|invite| {
+                        invite.room.room_id() == preview.room_id()
+                    })
+                {
+                    self.invite_preview = None;
+                }
+            }
+            Message::InviteSelected(room_id) => {
+                self.invite_preview = self
+                    .invites
+                    .iter()
+                    .find(|invite| invite.room.room_id() == room_id)
+                    .cloned()
+                    .map(InvitePreview::new);
+            }
+            Message::Invite(msg) => {
+                let Some(invite_preview) = &mut self.invite_preview else {
+                    return Action::None;
+                };
+                match invite_preview.update(msg) {
+                    invite::Action::None => (),
+                    invite::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Invite));
+                    }
+                    invite::Action::Joined(room) => {
+                        self.remove_invite(room.room_id());
+                        return self.show_room(room);
+                    }
+                    invite::Action::Declined(room_id) => {
+                        self.remove_invite(&room_id);
+                    }
+                }
+            }
+            Message::SpaceSelected(space_id) => {
+                let Some(space) =
+                    space_id.and_then(|id| self.client.get_room(&id))
+                else {
+                    self.space_tree = None;
+                    return Action::None;
+                };
+                let (space_tree, task) =
+                    SpaceTree::new(self.client.clone(), space);
+                self.space_tree = Some(space_tree);
+                return Action::Task(task.map(Message::Space));
+            }
+            Message::Space(msg) => {
+                let Some(space_tree) = &mut self.space_tree else {
+                    return Action::None;
+                };
+                match space_tree.update(msg) {
+                    space::Action::None => (),
+                    space::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Space));
+                    }
+                    space::Action::Open(room_id) => {
+                        return self.update(Message::RoomSelected(room_id));
+                    }
+                    // Joined subspaces turn up in the space bar after the
+                    // next sync
+                    space::Action::Joined(room) if room.is_space() => (),
+                    space::Action::Joined(room) => return self.show_room(room),
+                }
+            }
+            Message::RoomSelected(room_id) => {
+                let Some(room) = self.client.get_room(&room_id) else {
+                    return Action::None;
+                };
+                self.invite_preview = None;
+                return Action::Task(Task::perform(
+                    open_timeline(room.clone(), live_focus()),
+                    move |result| Message::TimelineOpened(room.clone(), result),
+                ));
+            }
+            Message::TimelineOpened(room, result) => match result {
+                Ok(timeline) => {
+                    let (room_view, task) = RoomView::new(
+                        room.clone(),
+                        timeline,
+                        self.media.clone(),
+                        self.search_index.clone(),
+                    );
+                    self.room_view = Some(room_view);
+                    self.thread_view = None;
+                    let mut tasks = vec![task.map(Message::RoomView)];
+                    // Keep the member list open, now showing the new room
+                    if self.member_list.is_some() {
+                        let (member_list, task) =
+                            MemberList::new(room, self.media.clone());
+                        self.member_list = Some(member_list);
+                        tasks.push(task.map(Message::MemberList));
+                    }
+                    return Action::Task(Task::batch(tasks));
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::RoomView(msg) => {
+                let Some(room_view) = &mut self.room_view else {
+                    return Action::None;
+                };
+                match room_view.update(msg) {
+                    room_view::Action::None => (),
+                    room_view::Action::Task(task) => {
+                        return Action::Task(task.map(Message::RoomView));
+                    }
+                    room_view::Action::OpenThread(root_event_id) => {
+                        return self.open_thread(root_event_id);
+                    }
+                    room_view::Action::PickReaction(item_id) => {
+                        self.emoji_picker.reset();
+                        self.reacting_to = Some((Pane::Room, item_id));
+                    }
+                    room_view::Action::Moderate(user_id, name, kind) => {
+                        return self.moderate(user_id, name, kind);
+                    }
+                }
+            }
+            Message::ThreadOpened(room, result) => match result {
+                Ok(timeline) => {
+                    let (thread_view, task) = RoomView::new(
+                        room,
+                        timeline,
+                        self.media.clone(),
+                        self.search_index.clone(),
+                    );
+                    self.thread_view = Some(thread_view);
+                    return Action::Task(task.map(Message::ThreadView));
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::ThreadView(msg) => {
+                let Some(thread_view) = &mut self.thread_view else {
+                    return Action::None;
+                };
+                match thread_view.update(msg) {
+                    room_view::Action::None => (),
+                    room_view::Action::Task(task) => {
+                        return Action::Task(task.map(Message::ThreadView));
+                    }
+                    room_view::Action::OpenThread(root_event_id) => {
+                        return self.open_thread(root_event_id);
+                    }
+                    room_view::Action::PickReaction(item_id) => {
+                        self.emoji_picker.reset();
+                        self.reacting_to = Some((Pane::Thread, item_id));
+                    }
+                    room_view::Action::Moderate(user_id, name, kind) => {
+                        return self.moderate(user_id, name, kind);
+                    }
+                }
+            }
+            Message::CloseThread => {
+                self.thread_view = None;
+            }
+            Message::ToggleMembers => {
+                if self.member_list.take().is_some() {
+                    return Action::None;
+                }
+                let Some(room) =
+                    self.room_view.as_ref().map(|view| view.room().clone())
+                else {
+                    return Action::None;
+                };
+                let (member_list, task) =
+                    MemberList::new(room, self.media.clone());
+                self.member_list = Some(member_list);
+                return Action::Task(task.map(Message::MemberList));
+            }
+            Message::MemberList(msg) => {
+                let Some(member_list) = &mut self.member_list else {
+                    return Action::None;
+                };
+                match member_list.update(msg) {
+                    member_list::Action::None => (),
+                    member_list::Action::Task(task) => {
+                        return Action::Task(task.map(Message::MemberList));
+                    }
+                    member_list::Action::Moderate(user_id, name, kind) => {
+                        return self.moderate(user_id, name, kind);
+                    }
+                    member_list::Action::Verify(user_id, name) => {
+                        let (verification, task) = Verification::outgoing(
+                            self.client.clone(),
+                            user_id,
+                            name,
+                        );
+                        self.verification = Some(verification);
+                        return Action::Task(task.map(Message::Verification));
+                    }
+                }
+            }
+            Message::EmojiPicker(msg) => match self.emoji_picker.update(msg) {
+                emoji_picker::Action::None => (),
+                emoji_picker::Action::Picked(key) => {
+                    let Some((pane, item_id)) = self.reacting_to.take() else {
+                        return Action::None;
+                    };
+                    let msg = room_view::Message::ToggleReaction(item_id, key);
+                    return self.update(match pane {
+                        Pane::Room => Message::RoomView(msg),
+                        Pane::Thread => Message::ThreadView(msg),
+                    });
+                }
+                emoji_picker::Action::Close => {
+                    self.reacting_to = None;
+                }
+            },
+            Message::NewRoom => {
+                self.create_room = Some(CreateRoom::new(self.client.clone()));
+            }
+            Message::CreateRoom(msg) => {
+                let Some(create_room) = &mut self.create_room else {
+                    return Action::None;
+                };
+                match create_room.update(msg) {
+                    create_room::Action::None => (),
+                    create_room::Action::Task(task) => {
+                        return Action::Task(task.map(Message::CreateRoom));
+                    }
+                    create_room::Action::Created(room) => {
+                        self.create_room = None;
+                        return self.show_room(room);
+                    }
+                    create_room::Action::Close => {
+                        self.create_room = None;
+                    }
+                }
+            }
+            Message::ExploreRooms => {
+                let (room_directory, task) =
+                    RoomDirectory::new(self.client.clone());
+                self.room_directory = Some(room_directory);
+                return Action::Task(task.map(Message::RoomDirectory));
+            }
+            Message::RoomDirectory(msg) => {
+                let Some(room_directory) = &mut self.room_directory else {
+                    return Action::None;
+                };
+                match room_directory.update(msg) {
+                    room_directory::Action::None => (),
+                    room_directory::Action::Task(task) => {
+                        return Action::Task(task.map(Message::RoomDirectory));
+                    }
+                    room_directory::Action::Joined(room) => {
+                        self.room_directory = None;
+                        return self.show_room(room);
+                    }
+                    room_directory::Action::Close => {
+                        self.room_directory = None;
+                    }
+                }
+            }
+            Message::NewDm => {
+                self.new_dm =
+                    Some(NewDm::new(self.client.clone(), self.media.clone()));
+            }
+            Message::StartDm(msg) => {
+                let Some(new_dm) = &mut self.new_dm else {
+                    return Action::None;
+                };
+                match new_dm.update(msg) {
+                    new_dm::Action::None => (),
+                    new_dm::Action::Task(task) => {
+                        return Action::Task(task.map(Message::StartDm));
+                    }
+                    new_dm::Action::Opened(room) => {
+                        self.new_dm = None;
+                        return self.show_room(room);
+                    }
+                    new_dm::Action::Close => {
+                        self.new_dm = None;
+                    }
+                }
+            }
+            Message::OpenRoomSettings => {
+                let Some(room) =
+                    self.room_view.as_ref().map(|view| view.room().clone())
+                else {
+                    return Action::None;
+                };
+                let (room_settings, task) =
+                    RoomSettings::new(room, self.media.clone());
+                self.room_settings = Some(room_settings);
+                return Action::Task(task.map(Message::RoomSettings));
+            }
+            Message::RoomSettings(msg) => {
+                let Some(room_settings) = &mut self.room_settings else {
+                    return Action::None;
+                };
+                match room_settings.update(msg) {
+                    room_settings::Action::None => (),
+                    room_settings::Action::Task(task) => {
+                        return Action::Task(task.map(Message::RoomSettings));
+                    }
+                    room_settings::Action::Close => {
+                        self.room_settings = None;
+                    }
+                }
+            }
+            Message::Moderation(msg) => {
+                let Some(moderation) = &mut self.moderation else {
+                    return Action::None;
+                };
+                match moderation.update(msg) {
+                    moderation::Action::None => (),
+                    moderation::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Moderation));
+                    }
+                    moderation::Action::Done => {
+                        self.moderation = None;
+                        // Reload the member list to show the new membership
+                        // or power level
+                        if self.member_list.is_some()
+                            && let Some(room_view) = &self.room_view
+                        {
+                            let room = room_view.room().clone();
+                            let (member_list, task) =
+                                MemberList::new(room, self.media.clone());
+                            self.member_list = Some(member_list);
+                            return Action::Task(task.map(Message::MemberList));
+                        }
+                    }
+                    moderation::Action::Close => self.moderation = None,
+                }
+            }
+            Message::SessionVerificationChanged(state) => {
+                self.session_verification = state;
+            }
+            Message::VerifySession => {
+                let Some(user_id) = self.client.user_id() else {
+                    return Action::None;
+                };
+                let (verification, task) = Verification::outgoing(
+                    self.client.clone(),
+                    user_id.to_owned(),
+                    String::from("your other sessions"),
+                );
+                self.verification = Some(verification);
+                return Action::Task(task.map(Message::Verification));
+            }
+            Message::VerificationRequested(request) => {
+                // Only one verification is shown at a time, the others wait
+                // their turn
+                if self.verification.is_some() {
+                    if !self
+                        .pending_verifications
+                        .iter()
+                        .any(|pending| pending.flow_id() == request.flow_id())
+                    {
+                        self.pending_verifications.push_back(request);
+                    }
+                    return Action::None;
+                }
+                let (verification, task) = Verification::incoming(request);
+                self.verification = Some(verification);
+                return Action::Task(task.map(Message::Verification));
+            }
+            Message::Verification(msg) => {
+                let Some(verification) = &mut self.verification else {
+                    return Action::None;
+                };
+                match verification.update(msg) {
+                    verification::Action::None => (),
+                    verification::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Verification));
+                    }
+                    verification::Action::Close(task) => {
+                        self.verification = None;
+                        return Action::Task(Task::batch([
+                            task.map(Message::Verification),
+                            self.next_verification(),
+                        ]));
+                    }
+                }
+            }
+            Message::EncryptionSetupNeeded(needed) => {
+                if needed {
+                    self.encryption_setup =
+                        Some(EncryptionSetup::new(self.client.clone()));
+                }
+            }
+            Message::EncryptionSetup(msg) => {
+                let Some(encryption_setup) = &mut self.encryption_setup else {
+                    return Action::None;
+                };
+                match encryption_setup.update(msg) {
+                    encryption_setup::Action::None => (),
+                    encryption_setup::Action::Task(task) => {
+                        return Action::Task(
+                            task.map(Message::EncryptionSetup),
+                        );
+                    }
+                    encryption_setup::Action::Close => {
+                        self.encryption_setup = None;
+                    }
+                }
+            }
+            Message::OpenSecuritySettings => {
+                let (security_settings, task) =
+                    SecuritySettings::new(self.client.clone());
+                self.security_settings = Some(security_settings);
+                return Action::Task(task.map(Message::SecuritySettings));
+            }
+            Message::SecuritySettings(msg) => {
+                let Some(security_settings) = &mut self.security_settings
+                else {
+                    return Action::None;
+                };
+                match security_settings.update(msg) {
+                    security_settings::Action::None => (),
+                    security_settings::Action::Task(task) => {
+                        return Action::Task(
+                            task.map(Message::SecuritySettings),
+                        );
+                    }
+                    security_settings::Action::Close => {
+                        self.security_settings = None;
+                    }
+                }
+            }
+            Message::OpenSessions => {
+                let (sessions, task) = Sessions::new(self.client.clone());
+                self.sessions = Some(sessions);
+                return Action::Task(task.map(Message::Sessions));
+            }
+            Message::Sessions(msg) => {
+                let Some(sessions) = &mut self.sessions else {
+                    return Action::None;
+                };
+                match sessions.update(msg) {
+                    sessions::Action::None => (),
+                    sessions::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Sessions));
+                    }
+                    sessions::Action::Close => self.sessions = None,
+                }
+            }
+            Message::SearchInput(query) => {
+                self.search_hits = self.search_index.search(&query);
+                self.search_query = query;
+            }
+            Message::ClearSearch => {
+                self.search_query.clear();
+                self.search_hits.clear();
+            }
+            Message::OpenSearchHit(room_id, event_id) => {
+                let Some(room) = self.client.get_room(&room_id) else {
+                    return Action::None;
+                };
+                self.invite_preview = None;
+                let focus = TimelineFocus::Event {
+                    target: event_id,
+                    num_context_events: SEARCH_CONTEXT_EVENTS,
+                    hide_threaded_events: true,
+                };
+                return Action::Task(Task::perform(
+                    open_timeline(room.clone(), focus),
+                    move |result| Message::TimelineOpened(room.clone(), result),
+                ));
+            }
+            Message::SearchIndexLoaded(result) => match result {
+                Ok(()) => {
+                    self.search_hits =
+                        self.search_index.search(&self.search_query);
+                }
+                Err(error) => {
+                    self.search_error = Some(format!(
+                        "Couldn't read the search index, starting a new one: \
+                         {error}"
+                    ));
+                }
+            },
+            Message::SearchIndexSaved(result) => {
+                if let Err(error) = result {
+                    self.search_error = Some(format!(
+                        "Couldn't save the search index: {error}"
+                    ));
+                }
+            }
+            Message::DismissSearchError => self.search_error = None,
+        }
+
         Action::None
     }
 
     pub fn view(&self) -> Element<'_, Message> {
-        row![].into()
+        let selected_invite = self
+            .invite_preview
+            .as_ref()
+            .map(|preview| preview.room_id());
+        let selected = match selected_invite {
+            Some(_) => None,
+            None => self.room_view.as_ref().map(|view| view.room().room_id()),
+        };
+
+        let mut rooms: Vec<Element<Message>> = vec![
+            row![
+                button(text("New room").size(FONT_SIZE))
+                    .width(Length::Fill)
+                    .style(button::secondary)
+                    .on_press(Message::NewRoom),
+                button(text("New DM").size(FONT_SIZE))
+                    .width(Length::Fill)
+                    .style(button::secondary)
+                    .on_press(Message::NewDm),
+                button(text("Explore").size(FONT_SIZE))
+                    .width(Length::Fill)
+                    .style(button::secondary)
+                    .on_press(Message::ExploreRooms)
+            ]
+            .spacing(5)
+            .into(),
+        ];
+        if self.session_verification == VerificationState::Unverified {
+            rooms.push(
+                row![
+                    text("This session is unverified")
+                        .size(FONT_SIZE - 1)
+                        .width(Length::Fill),
+                    button(text("Verify").size(FONT_SIZE - 1))
+                        .on_press(Message::VerifySession)
+                ]
+                .spacing(5)
+                .align_y(Alignment::Center)
+                .into(),
+            );
+        }
+        if !self.invites.is_empty() {
+            rooms.push(
+                text(format!("Invites — {}", self.invites.len()))
+                    .size(FONT_SIZE - 1)
+                    .into(),
+            );
+            for invite in &self.invites {
+                let room_id = invite.room.room_id();
+                rooms.push(invite.view(
+                    selected_invite == Some(room_id),
+                    Message::InviteSelected(room_id.to_owned()),
+                ));
+            }
+            if self.space_tree.is_none() {
+                rooms.push(text("Rooms").size(FONT_SIZE - 1).into());
+            }
+        }
+        // Inside a space its tree takes the place of the flat room list
+        if let Some(space_tree) = &self.space_tree {
+            rooms.push(
+                text(room_name(space_tree.space()))
+                    .size(FONT_SIZE + 2)
+                    .into(),
+            );
+            rooms.push(space_tree.view(selected).map(Message::Space));
+        } else {
+            for room in &self.rooms {
+                let is_selected = selected == Some(room.room_id());
+                rooms.push(
+                    button(text(room_name(room)).size(FONT_SIZE))
+                        .width(Length::Fill)
+                        .style(if is_selected {
+                            button::primary
+                        } else {
+                            button::text
+                        })
+                        .on_press(Message::RoomSelected(
+                            room.room_id().to_owned(),
+                        ))
+                        .into(),
+                );
+            }
+        }
+        if let Some(error) = &self.error {
+            rooms.push(text(error).size(FONT_SIZE).into());
+        }
+
+        let account = row![
+            text(self.client.user_id().map_or("", |user_id| user_id.as_str()))
+                .size(FONT_SIZE - 1)
+                .width(Length::Fill),
+            button(text("Sessions").size(FONT_SIZE - 1))
+                .style(button::secondary)
+                .on_press(Message::OpenSessions),
+            button(text("Security").size(FONT_SIZE - 1))
+                .style(button::secondary)
+                .on_press(Message::OpenSecuritySettings)
+        ]
+        .spacing(5)
+        .padding(10)
+        .align_y(Alignment::Center);
+        if !self.search_query.trim().is_empty() {
+            rooms = self.view_search_hits();
+        }
+
+        let mut search = row![
+            text_input("Search messages", &self.search_query)
+                .on_input(Message::SearchInput)
+                .size(FONT_SIZE)
+        ]
+        .spacing(5)
+        .padding([0, 10])
+        .align_y(Alignment::Center);
+        if !self.search_query.is_empty() {
+            search = search.push(
+                button(text("Clear").size(FONT_SIZE - 1))
+                    .style(button::secondary)
+                    .on_press(Message::ClearSearch),
+            );
+        }
+        let mut search = column![search].spacing(5);
+        if let Some(error) = &self.search_error {
+            search = search.push(
+                row![
+                    text(error).size(FONT_SIZE - 1).width(Length::Fill),
+                    button(text("Dismiss").size(FONT_SIZE - 1))
+                        .style(button::text)
+                        .on_press(Message::DismissSearchError)
+                ]
+                .spacing(5)
+                .padding([0, 10])
+                .align_y(Alignment::Center),
+            );
+        }
+        let room_list = column![
+            container(search).padding([10, 0]),
+            rule::horizontal(1),
+            scrollable(Column::with_children(rooms).spacing(2).padding(10))
+                .height(Length::Fill),
+            rule::horizontal(1),
+            account
+        ]
+        .width(ROOM_LIST_WIDTH)
+        .height(Length::Fill);
+
+        let selected_space =
+            self.space_tree.as_ref().map(|tree| tree.space().room_id());
+        let mut spaces: Vec<Element<Message>> =
+            vec![space_button("home", "Home", selected_space.is_none(), None)];
+        for space in &self.spaces {
+            spaces.push(space_button(
+                space.room_id().as_str(),
+                &room_name(space),
+                selected_space == Some(space.room_id()),
+                Some(space.room_id().to_owned()),
+            ));
+        }
+        let space_bar = container(scrollable(
+            Column::with_children(spaces)
+                .spacing(6)
+                .padding(8)
+                .align_x(Alignment::Center),
+        ))
+        .width(SPACE_BAR_WIDTH)
+        .height(Length::Fill);
+
+        let content: Element<Message> =
+            match (&self.invite_preview, &self.room_view) {
+                (Some(invite_preview), _) => {
+                    invite_preview.view().map(Message::Invite)
+                }
+                (None, Some(room_view)) => {
+                    let header = row![
+                        text(room_name(room_view.room()))
+                            .size(FONT_SIZE + 2)
+                            .width(Length::Fill),
+                        button(text("Members").size(FONT_SIZE))
+                            .style(if self.member_list.is_some() {
+                                button::primary
+                            } else {
+                                button::secondary
+                            })
+                            .on_press(Message::ToggleMembers),
+                        button(text("Settings").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press(Message::OpenRoomSettings)
+                    ]
+                    .spacing(5)
+                    .align_y(Alignment::Center)
+                    .padding(10);
+
+                    column![
+                        header,
+                        rule::horizontal(1),
+                        room_view.view().map(Message::RoomView)
+                    ]
+                    .into()
+                }
+                (None, None) => {
+                    center(text("Select a room").size(FONT_SIZE)).into()
+                }
+            };
+
+        let mut screen = row![
+            space_bar,
+            rule::vertical(1),
+            room_list,
+            rule::vertical(1),
+            content
+        ];
+
+        if let Some(member_list) = &self.member_list {
+            screen = screen.push(rule::vertical(1)).push(
+                container(member_list.view().map(Message::MemberList))
+                    .width(MEMBER_LIST_WIDTH)
+                    .height(Length::Fill),
+            );
+        }
+
+        if let Some(thread_view) = &self.thread_view {
+            let header = row![
+                text("Thread").size(FONT_SIZE + 2).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press(Message::CloseThread)
+            ]
+            .align_y(Alignment::Center)
+            .padding(10);
+
+            screen = screen.push(rule::vertical(1)).push(
+                column![
+                    header,
+                    rule::horizontal(1),
+                    thread_view.view().map(Message::ThreadView)
+                ]
+                .width(THREAD_PANEL_WIDTH),
+            );
+        }
+
+        if let Some(verification) = &self.verification {
+            modal(
+                screen,
+                verification.view().map(Message::Verification),
+                Message::Verification(verification::Message::Dismiss),
+            )
+        } else if let Some(encryption_setup) = &self.encryption_setup {
+            modal(
+                screen,
+                encryption_setup.view().map(Message::EncryptionSetup),
+                Message::EncryptionSetup(encryption_setup::Message::Close),
+            )
+        } else if let Some(security_settings) = &self.security_settings {
+            modal(
+                screen,
+                security_settings.view().map(Message::SecuritySettings),
+                Message::SecuritySettings(security_settings::Message::Close),
+            )
+        } else if let Some(sessions) = &self.sessions {
+            modal(
+                screen,
+                sessions.view().map(Message::Sessions),
+                Message::Sessions(sessions::Message::Close),
+            )
+        } else if let Some(create_room) = &self.create_room {
+            modal(
+                screen,
+                create_room.view().map(Message::CreateRoom),
+                Message::CreateRoom(create_room::Message::Close),
+            )
+        } else if let Some(room_directory) = &self.room_directory {
+            modal(
+                screen,
+                room_directory.view().map(Message::RoomDirectory),
+                Message::RoomDirectory(room_directory::Message::Close),
+            )
+        } else if let Some(new_dm) = &self.new_dm {
+            modal(
+                screen,
+                new_dm.view().map(Message::StartDm),
+                Message::StartDm(new_dm::Message::Close),
+            )
+        } else if let Some(room_settings) = &self.room_settings {
+            modal(
+                screen,
+                room_settings.view().map(Message::RoomSettings),
+                Message::RoomSettings(room_settings::Message::Close),
+            )
+        } else if let Some(moderation) = &self.moderation {
+            modal(
+                screen,
+                moderation.view().map(Message::Moderation),
+                Message::Moderation(moderation::Message::Close),
+            )
+        } else if self.reacting_to.is_some() {
+            modal(
+                screen,
+                self.emoji_picker.view().map(Message::EmojiPicker),
+                Message::EmojiPicker(emoji_picker::Message::Close),
+            )
+        } else {
+            screen.into()
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        // Files dropped onto the window are attached to the open room
+        if self.room_view.is_none() {
+            return Subscription::none();
+        }
+        event::listen_with(|event, _status, _window| {
+            let Event::Window(event) = event else {
+                return None;
+            };
+            let msg = match event {
+                window::Event::FileHovered(_path) => {
+                    room_view::Message::FilesHovered(true)
+                }
+                window::Event::FilesHoveredLeft => {
+                    room_view::Message::FilesHovered(false)
+                }
+                window::Event::FileDropped(path) => {
+                    room_view::Message::FileDropped(path)
+                }
+                _ => return None,
+            };
+            Some(Message::RoomView(msg))
+        })
+    }
+
+    /// Opens a room that was just joined or created, adding it to the room
+    /// list straight away rather than after the next sync.
+    fn show_room(&mut self, room: Room) -> Action {
+        if !self.rooms.iter().any(|r| r.room_id() == room.room_id()) {
+            self.rooms.push(room.clone());
+            self.rooms.sort_by_key(room_name);
+        }
+        self.update(Message::RoomSelected(room.room_id().to_owned()))
+    }
+
+    /// Search results in place of the room list: where and when each message
+    /// was sent, and a snippet with the matching words highlighted.
+    fn view_search_hits(&self) -> Vec<Element<'_, Message>> {
+        if self.search_hits.is_empty() {
+            return vec![text("No messages found").size(FONT_SIZE).into()];
+        }
+
+        self.search_hits
+            .iter()
+            .map(|hit| {
+                let room = self.client.get_room(&hit.room_id).map_or_else(
+                    || hit.room_id.to_string(),
+                    |room| room_name(&room),
+                );
+                let snippet: Vec<Span<'_, ()>> = hit
+                    .snippet
+                    .iter()
+                    .map(|(part, highlighted)| {
+                        let part = span(part.as_str());
+                        if *highlighted {
+                            part.font(BOLD).background(Color::from_rgba(
+                                1.0, 0.85, 0.2, 0.3,
+                            ))
+                        } else {
+                            part
+                        }
+                    })
+                    .collect();
+                button(
+                    column![
+                        row![
+                            text(room)
+                                .size(FONT_SIZE - 1)
+                                .font(BOLD)
+                                .width(Length::Fill),
+                            text(format_timestamp(hit.timestamp))
+                                .size(FONT_SIZE - 3)
+                        ]
+                        .spacing(5),
+                        text(hit.sender.as_str()).size(FONT_SIZE - 2),
+                        rich_text(snippet).size(FONT_SIZE - 1)
+                    ]
+                    .spacing(2),
+                )
+                .width(Length::Fill)
+                .style(button::text)
+                .on_press(Message::OpenSearchHit(
+                    hit.room_id.clone(),
+                    hit.event_id.clone(),
+                ))
+                .into()
+            })
+            .collect()
+    }
+
+    fn remove_invite(&mut self, room_id: &RoomId) {
+        self.invites
+            .retain(|invite| invite.room.room_id() != room_id);
+        self.invite_preview = None;
+    }
+
+    fn open_thread(&mut self, root_event_id: OwnedEventId) -> Action {
+        let Some(room) =
+            self.room_view.as_ref().map(|view| view.room().clone())
+        else {
+            return Action::None;
+        };
+        Action::Task(Task::perform(
+            open_timeline(
+                room.clone(),
+                TimelineFocus::Thread { root_event_id },
+            ),
+            move |result| Message::ThreadOpened(room.clone(), result),
+        ))
+    }
+
+    /// Shows the next queued request that is still waiting for an answer.
+    fn next_verification(&mut self) -> Task<Message> {
+        while let Some(request) = self.pending_verifications.pop_front() {
+            if request.is_cancelled() || request.is_done() {
+                continue;
+            }
+            let (verification, task) = Verification::incoming(request);
+            self.verification = Some(verification);
+            return task.map(Message::Verification);
+        }
+        Task::none()
+    }
+
+    fn moderate(
+        &mut self,
+        user_id: OwnedUserId,
+        name: String,
+        kind: moderation::Kind,
+    ) -> Action {
+        let Some(room) =
+            self.room_view.as_ref().map(|view| view.room().clone())
+        else {
+            return Action::None;
+        };
+        let (moderation, task) = Moderation::new(room, user_id, name, kind);
+        self.moderation = Some(moderation);
+        Action::Task(task.map(Message::Moderation))
+    }
+}
+
+pub fn format_date(timestamp: MilliSecondsSinceUnixEpoch) -> String {
+    format_local(timestamp, "%A, %-d %B %Y")
+}
+
+pub fn format_timestamp(timestamp: MilliSecondsSinceUnixEpoch) -> String {
+    format_local(timestamp, "%Y-%m-%d %H:%M")
+}
+
+fn format_local(timestamp: MilliSecondsSinceUnixEpoch, format: &str) -> String {
+    DateTime::from_timestamp_millis(timestamp.get().into())
+        .map(|time| time.with_timezone(&Local).format(format).to_string())
+        .unwrap_or_default()
+}
+
+/// An entry in the space bar. `space` is None for the button showing every
+/// room.
+fn space_button<'a>(
+    id: &str,
+    name: &str,
+    is_selected: bool,
+    space: Option<OwnedRoomId>,
+) -> Element<'a, Message> {
+    tooltip(
+        button(avatar::view(id, name, SPACE_ICON_SIZE))
+            .padding(2)
+            .style(if is_selected {
+                button::primary
+            } else {
+                button::text
+            })
+            .on_press(Message::SpaceSelected(space)),
+        container(text(name.to_string()).size(FONT_SIZE))
+            .padding(5)
+            .style(container::rounded_box),
+        tooltip::Position::Right,
+    )
+    .into()
+}
+
+fn room_name(room: &Room) -> String {
+    room.cached_display_name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| room.room_id().to_string())
+}
+
+/// Password confirmation for a request the server wants authenticated,
+/// continuing the session it started.
+fn password_auth(
+    user_id: &UserId,
+    session: Option<String>,
+    password: String,
+) -> AuthData {
+    let mut auth = Password::new(
+        UserIdentifier::UserIdOrLocalpart(user_id.to_string()),
+        password,
+    );
+    auth.session = session;
+    AuthData::Password(auth)
+}
+
+/// Syncs with the homeserver forever, yielding after every response.
+pub fn sync(client: Client) -> impl Stream<Item = Result<(), String>> {
+    stream::unfold(client, |client| async move {
+        let result = client
+            .sync_once(SyncSettings::default().timeout(SYNC_TIMEOUT))
+            .await
+            .map(|_response| ())
+            .map_err(|error| error.to_string());
+        if result.is_err() {
+            sleep(SYNC_RETRY_DELAY).await;
+        }
+        Some((result, client))
+    })
+}
+
+/// In-thread replies live in the thread panel rather than the main timeline.
+fn live_focus() -> TimelineFocus {
+    TimelineFocus::Live {
+        hide_threaded_events: true,
+    }
+}
+
+async fn open_timeline(
+    room: Room,
+    focus: TimelineFocus,
+) -> Result<Arc<Timeline>, String> {
+    let timeline = room
+        .timeline_builder()
+        .with_focus(focus)
+        .track_read_marker_and_receipts(
+            TimelineReadReceiptTracking::MessageLikeEvents,
+        )
+        .build()
+        .await;
+    match timeline {
+        Ok(timeline) => Ok(Arc::new(timeline)),
+        Err(error) => Err(error.to_string()),
     }
 }
@@ -0,0 +1,60 @@
+// Round placeholder avatars showing the first letter of a name
+use iced::Background;
+use iced::Border;
+use iced::Color;
+use iced::Element;
+use iced::Theme;
+use iced::widget::container;
+use iced::widget::image;
+use iced::widget::text;
+
+// Picked so white text stays readable on all of them
+const COLORS: [Color; 6] = [
+    Color::from_rgb(0.05, 0.55, 0.55),
+    Color::from_rgb(0.35, 0.35, 0.75),
+    Color::from_rgb(0.70, 0.30, 0.45),
+    Color::from_rgb(0.30, 0.55, 0.25),
+    Color::from_rgb(0.75, 0.40, 0.10),
+    Color::from_rgb(0.45, 0.30, 0.65),
+];
+
+/// A circle of the given diameter. `id` picks the colour so the same user
+/// always gets the same one, `name` provides the letter.
+pub fn view<'a, Message: 'a>(
+    id: &str,
+    name: &str,
+    size: f32,
+) -> Element<'a, Message> {
+    let letter = name
+        .trim_start_matches(['@', '#', '!'])
+        .chars()
+        .next()
+        .map(|letter| letter.to_uppercase().to_string())
+        .unwrap_or_default();
+    let color =
+        COLORS[id.bytes().map(usize::from).sum::<usize>() % COLORS.len()];
+
+    container(text(letter).size(size * 0.55).color(Color::WHITE))
+        .center(size)
+        .style(move |_theme: &Theme| container::Style {
+            background: Some(Background::Color(color)),
+            border: Border {
+                radius: (size / 2.0).into(),
+                ..Border::default()
+            },
+            ..container::Style::default()
+        })
+        .into()
+}
+
+/// A picture cropped to a circle of the given diameter.
+pub fn picture<'a, Message: 'a>(
+    handle: &image::Handle,
+    size: f32,
+) -> Element<'a, Message> {
+    image(handle.clone())
+        .width(size)
+        .height(size)
+        .border_radius(size / 2.0)
+        .into()
+}
@@ -0,0 +1,366 @@
+// Dialog for creating a new room
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::checkbox;
+use iced::widget::column;
+use iced::widget::pick_list;
+use iced::widget::radio;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::api::client::room::Visibility;
+use matrix_sdk::ruma::api::client::room::create_room::v3::Request;
+use matrix_sdk::ruma::api::client::room::create_room::v3::RoomPreset;
+use matrix_sdk::ruma::events::InitialStateEvent;
+use matrix_sdk::ruma::events::room::encryption::RoomEncryptionEventContent;
+use matrix_sdk::ruma::events::room::history_visibility::HistoryVisibility;
+use matrix_sdk::ruma::events::room::history_visibility::RoomHistoryVisibilityEventContent;
+use std::fmt;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 400.0;
+const LABEL_WIDTH: f32 = 75.0;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Created(Room),
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Private,
+    Public,
+}
+
+/// Who can read messages sent before they joined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum History {
+    Shared,
+    Invited,
+    Joined,
+    WorldReadable,
+}
+
+impl History {
+    pub const ALL: [History; 4] = [
+        History::Shared,
+        History::Invited,
+        History::Joined,
+        History::WorldReadable,
+    ];
+
+    pub fn visibility(self) -> HistoryVisibility {
+        match self {
+            History::Shared => HistoryVisibility::Shared,
+            History::Invited => HistoryVisibility::Invited,
+            History::Joined => HistoryVisibility::Joined,
+            History::WorldReadable => HistoryVisibility::WorldReadable,
+        }
+    }
+
+    pub fn from_visibility(visibility: &HistoryVisibility) -> Self {
+        match visibility {
+            HistoryVisibility::Invited => History::Invited,
+            HistoryVisibility::Joined => History::Joined,
+            HistoryVisibility::WorldReadable => History::WorldReadable,
+            // Shared is the default for unknown values too
+            _ => History::Shared,
+        }
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            History::Shared => "Members, including before they joined",
+            History::Invited => "Members, since they were invited",
+            History::Joined => "Members, since they joined",
+            History::WorldReadable => "Anyone",
+        })
+    }
+}
+
+pub struct CreateRoom {
+    client: Client,
+    name: String,
+    topic: String,
+    alias: String,
+    access: Access,
+    encrypted: bool,
+    history: History,
+    invitees: String,
+    creating: bool,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    NameInput(String),
+    TopicInput(String),
+    AliasInput(String),
+    AccessSelected(Access),
+    ToggleEncryption(bool),
+    HistorySelected(History),
+    InviteesInput(String),
+    Create,
+    Created(Result<Room, String>),
+    Close,
+}
+
+impl CreateRoom {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            name: String::new(),
+            topic: String::new(),
+            alias: String::new(),
+            access: Access::Private,
+            encrypted: true,
+            history: History::Shared,
+            invitees: String::new(),
+            creating: false,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::NameInput(name) => self.name = name,
+            Message::TopicInput(topic) => self.topic = topic,
+            Message::AliasInput(alias) => self.alias = alias,
+            Message::AccessSelected(access) => self.access = access,
+            Message::ToggleEncryption(encrypted) => self.encrypted = encrypted,
+            Message::HistorySelected(history) => self.history = history,
+            Message::InviteesInput(invitees) => self.invitees = invitees,
+            Message::Create => {
+                let request = match self.request() {
+                    Ok(request) => request,
+                    Err(error) => {
+                        self.error = Some(error);
+                        return Action::None;
+                    }
+                };
+                self.creating = true;
+                self.error = None;
+                let client = self.client.clone();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .create_room(request)
+                            .await
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::Created,
+                ));
+            }
+            Message::Created(result) => {
+                self.creating = false;
+                match result {
+                    Ok(room) => return Action::Created(room),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut items: Vec<Element<Message>> = vec![
+            text("New room").size(20).into(),
+            rule::horizontal(1).into(),
+            field(
+                "Name:",
+                text_input("Room name", &self.name)
+                    .on_input(Message::NameInput)
+                    .size(FONT_SIZE),
+            ),
+            field(
+                "Topic:",
+                text_input("Optional", &self.topic)
+                    .on_input(Message::TopicInput)
+                    .size(FONT_SIZE),
+            ),
+            field(
+                "Address:",
+                row![
+                    text("#").size(FONT_SIZE),
+                    text_input("Optional", &self.alias)
+                        .on_input(Message::AliasInput)
+                        .size(FONT_SIZE),
+                    text(format!(":{}", self.server_name())).size(FONT_SIZE)
+                ]
+                .spacing(4)
+                .align_y(Alignment::Center),
+            ),
+            field(
+                "Access:",
+                column![
+                    radio(
+                        "Private, invite only",
+                        Access::Private,
+                        Some(self.access),
+                        Message::AccessSelected
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE),
+                    radio(
+                        "Public, listed in the room directory",
+                        Access::Public,
+                        Some(self.access),
+                        Message::AccessSelected
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE)
+                ]
+                .spacing(6),
+            ),
+            field(
+                "History:",
+                pick_list(
+                    History::ALL,
+                    Some(self.history),
+                    Message::HistorySelected,
+                )
+                .text_size(FONT_SIZE)
+                .width(Length::Fill),
+            ),
+            field(
+                "",
+                checkbox(self.encrypted)
+                    .label("End-to-end encryption")
+                    .on_toggle(Message::ToggleEncryption)
+                    .size(14)
+                    .text_size(FONT_SIZE),
+            ),
+            field(
+                "Invite:",
+                text_input(
+                    "@alice:example.org, @bob:example.org",
+                    &self.invitees,
+                )
+                .on_input(Message::InviteesInput)
+                .size(FONT_SIZE),
+            ),
+        ];
+
+        if self.encrypted && self.access == Access::Public {
+            items.push(
+                text(
+                    "Encryption can't be turned off later and makes \
+                     searching public rooms harder.",
+                )
+                .size(FONT_SIZE - 2)
+                .into(),
+            );
+        }
+        if let Some(error) = &self.error {
+            items.push(text(error).size(FONT_SIZE).into());
+        }
+
+        let mut buttons = row![].spacing(10).align_y(Alignment::Center);
+        if self.creating {
+            buttons = buttons.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        buttons = buttons.push(
+            button(text("Cancel").size(FONT_SIZE))
+                .style(button::secondary)
+                .on_press(Message::Close),
+        );
+        buttons = buttons.push(
+            button(text("Create").size(FONT_SIZE)).on_press_maybe(
+                (!self.creating && !self.name.trim().is_empty())
+                    .then_some(Message::Create),
+            ),
+        );
+        items.push(
+            row![text("").width(Length::Fill), buttons]
+                .align_y(Alignment::Center)
+                .into(),
+        );
+
+        Column::with_children(items).spacing(12).width(WIDTH).into()
+    }
+
+    fn server_name(&self) -> &str {
+        self.client
+            .user_id()
+            .map(|user_id| user_id.server_name().as_str())
+            .unwrap_or_default()
+    }
+
+    fn request(&self) -> Result<Request, String> {
+        let invite = self
+            .invitees
+            .split([',', ' ', '\n'])
+            .filter(|user_id| !user_id.is_empty())
+            .map(|user_id| {
+                UserId::parse(user_id)
+                    .map_err(|_error| format!("{user_id} is not a user ID"))
+            })
+            .collect::<Result<Vec<OwnedUserId>, String>>()?;
+
+        let mut initial_state = vec![
+            InitialStateEvent::with_empty_state_key(
+                RoomHistoryVisibilityEventContent::new(
+                    self.history.visibility(),
+                ),
+            )
+            .to_raw_any(),
+        ];
+        if self.encrypted {
+            initial_state.push(
+                InitialStateEvent::with_empty_state_key(
+                    RoomEncryptionEventContent::with_recommended_defaults(),
+                )
+                .to_raw_any(),
+            );
+        }
+
+        let mut request = Request::new();
+        request.name = Some(self.name.trim().to_string());
+        request.topic = Some(self.topic.trim().to_string())
+            .filter(|topic| !topic.is_empty());
+        request.room_alias_name =
+            Some(self.alias.trim().trim_start_matches('#').to_string())
+                .filter(|alias| !alias.is_empty());
+        (request.preset, request.visibility) = match self.access {
+            Access::Private => {
+                (Some(RoomPreset::PrivateChat), Visibility::Private)
+            }
+            Access::Public => {
+                (Some(RoomPreset::PublicChat), Visibility::Public)
+            }
+        };
+        request.initial_state = initial_state;
+        request.invite = invite;
+
+        Ok(request)
+    }
+}
+
+fn field<'a>(
+    label: &'a str,
+    input: impl Into<Element<'a, Message>>,
+) -> Element<'a, Message> {
+    row![text(label).size(FONT_SIZE).width(LABEL_WIDTH), input.into()]
+        .spacing(10)
+        .align_y(Alignment::Center)
+        .into()
+}
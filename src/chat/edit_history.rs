@@ -0,0 +1,160 @@
+// Lists every version of an edited message, oldest first
+use crate::chat::format_timestamp;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use matrix_sdk::Room;
+use matrix_sdk::room::IncludeRelations;
+use matrix_sdk::room::RelationsOptions;
+use matrix_sdk::ruma::MilliSecondsSinceUnixEpoch;
+use matrix_sdk::ruma::OwnedEventId;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::UInt;
+use matrix_sdk::ruma::api::Direction;
+use matrix_sdk::ruma::events::AnySyncMessageLikeEvent;
+use matrix_sdk::ruma::events::AnySyncTimelineEvent;
+use matrix_sdk::ruma::events::SyncMessageLikeEvent;
+use matrix_sdk::ruma::events::relation::RelationType;
+use matrix_sdk::ruma::events::room::message::OriginalSyncRoomMessageEvent;
+use matrix_sdk::ruma::events::room::message::Relation;
+use matrix_sdk::ruma::serde::Raw;
+use matrix_sdk_ui::timeline::EventTimelineItem;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 450.0;
+const MAX_HEIGHT: f32 = 400.0;
+const MAX_EDITS: u32 = 50;
+
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+    pub body: String,
+}
+
+pub enum EditHistory {
+    Loading,
+    Loaded(Vec<Revision>),
+    Error(String),
+}
+
+impl EditHistory {
+    pub fn view<Message: Clone + 'static>(
+        &self,
+        on_close: Message,
+    ) -> Element<'_, Message> {
+        let content: Element<Message> = match self {
+            EditHistory::Loading => center_x(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            )
+            .into(),
+            EditHistory::Error(error) => text(error).size(FONT_SIZE).into(),
+            EditHistory::Loaded(revisions) => scrollable(
+                Column::with_children(revisions.iter().rev().map(|revision| {
+                    column![
+                        text(format_timestamp(revision.timestamp))
+                            .size(FONT_SIZE - 2),
+                        text(&revision.body).size(FONT_SIZE)
+                    ]
+                    .spacing(2)
+                    .into()
+                }))
+                .spacing(10),
+            )
+            .into(),
+        };
+
+        container(
+            column![
+                row![
+                    text("Edit history").size(20).width(Length::Fill),
+                    button(text("Close").size(FONT_SIZE)).on_press(on_close)
+                ]
+                .align_y(Alignment::Center),
+                rule::horizontal(1),
+                content
+            ]
+            .spacing(10),
+        )
+        .width(WIDTH)
+        .max_height(MAX_HEIGHT)
+        .into()
+    }
+}
+
+/// Fetches the `m.replace` relations of an event. `original` is the first
+/// version, which the server doesn't return as a relation. Only edits by
+/// `sender`, who sent the original, are real revisions.
+pub async fn load(
+    room: Room,
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    original: Revision,
+) -> Result<Vec<Revision>, String> {
+    let relations = room
+        .relations(
+            event_id,
+            RelationsOptions {
+                dir: Direction::Forward,
+                limit: Some(UInt::from(MAX_EDITS)),
+                include_relations: IncludeRelations::RelationsOfType(
+                    RelationType::Replacement,
+                ),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let mut revisions = vec![original];
+    for event in relations.chunk {
+        let Some(event) = room_message(event.raw()) else {
+            continue;
+        };
+        if event.sender != sender {
+            continue;
+        }
+        if let Some(Relation::Replacement(replacement)) =
+            event.content.relates_to
+        {
+            revisions.push(Revision {
+                timestamp: event.origin_server_ts,
+                body: replacement.new_content.msgtype.body().to_string(),
+            });
+        }
+    }
+
+    Ok(revisions)
+}
+
+/// The first version of an edited message, taken from its original event.
+pub fn original(event: &EventTimelineItem) -> Option<Revision> {
+    let original = room_message(event.original_json()?)?;
+    Some(Revision {
+        timestamp: original.origin_server_ts,
+        body: original.content.msgtype.body().to_string(),
+    })
+}
+
+fn room_message(
+    raw: &Raw<AnySyncTimelineEvent>,
+) -> Option<OriginalSyncRoomMessageEvent> {
+    match raw.deserialize().ok()? {
+        AnySyncTimelineEvent::MessageLike(
+            AnySyncMessageLikeEvent::RoomMessage(
+                SyncMessageLikeEvent::Original(event),
+            ),
+        ) => Some(event),
+        _ => None,
+    }
+}
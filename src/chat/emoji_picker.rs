@@ -0,0 +1,231 @@
+// Searchable emoji grid used to pick reactions
+use emojis::Emoji;
+use emojis::Group;
+use emojis::SkinTone;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::widget::Row;
+use iced::widget::button;
+use iced::widget::column;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use iced::widget::text_input;
+use iced::widget::tooltip;
+
+const FONT_SIZE: u32 = 13;
+const EMOJI_SIZE: u32 = 20;
+const WIDTH: f32 = 360.0;
+const HEIGHT: f32 = 380.0;
+const MAX_RECENT: usize = 24;
+const MAX_SEARCH_RESULTS: usize = 200;
+const SKIN_TONES: [SkinTone; 6] = [
+    SkinTone::Default,
+    SkinTone::Light,
+    SkinTone::MediumLight,
+    SkinTone::Medium,
+    SkinTone::MediumDark,
+    SkinTone::Dark,
+];
+
+pub enum Action {
+    None,
+    Picked(String),
+    Close,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tab {
+    Recent,
+    Group(Group),
+}
+
+pub struct EmojiPicker {
+    search: String,
+    tab: Tab,
+    skin_tone: SkinTone,
+    // Most recent first
+    recent: Vec<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    SearchInput(String),
+    TabSelected(Tab),
+    SkinToneSelected(SkinTone),
+    Picked(String),
+    Close,
+}
+
+impl EmojiPicker {
+    pub fn new() -> Self {
+        Self {
+            search: String::new(),
+            tab: Tab::Group(Group::SmileysAndEmotion),
+            skin_tone: SkinTone::Default,
+            recent: Vec::new(),
+        }
+    }
+
+    /// Clears the search and shows recently used emoji if there are any.
+    pub fn reset(&mut self) {
+        self.search.clear();
+        if !self.recent.is_empty() {
+            self.tab = Tab::Recent;
+        }
+    }
+
+    /// Moves an emoji to the front of the recently used list.
+    pub fn mark_used(&mut self, key: &str) {
+        self.recent.retain(|recent| recent != key);
+        self.recent.insert(0, key.to_string());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::SearchInput(string) => {
+                self.search = string;
+            }
+            Message::TabSelected(tab) => {
+                self.tab = tab;
+                self.search.clear();
+            }
+            Message::SkinToneSelected(skin_tone) => {
+                self.skin_tone = skin_tone;
+            }
+            Message::Picked(key) => {
+                self.mark_used(&key);
+                return Action::Picked(key);
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let search = text_input("Search emoji", &self.search)
+            .on_input(Message::SearchInput)
+            .size(FONT_SIZE);
+
+        let skin_tones =
+            Row::with_children(SKIN_TONES.into_iter().map(|tone| {
+                let hand = emojis::get("✋")
+                    .and_then(|hand| hand.with_skin_tone(tone))
+                    .map(Emoji::as_str)
+                    .unwrap_or("✋");
+                button(text(hand).size(FONT_SIZE + 2))
+                    .style(if tone == self.skin_tone {
+                        button::primary
+                    } else {
+                        button::text
+                    })
+                    .padding(2)
+                    .on_press(Message::SkinToneSelected(tone))
+                    .into()
+            }));
+
+        let mut tabs = vec![(Tab::Recent, "🕘")];
+        tabs.extend(Group::iter().map(|group| {
+            let icon = group.emojis().next().map(Emoji::as_str).unwrap_or("?");
+            (Tab::Group(group), icon)
+        }));
+        let tabs = Row::with_children(tabs.into_iter().map(|(tab, icon)| {
+            tooltip(
+                button(text(icon).size(FONT_SIZE + 2))
+                    .style(if tab == self.tab && self.search.is_empty() {
+                        button::primary
+                    } else {
+                        button::text
+                    })
+                    .padding(4)
+                    .on_press(Message::TabSelected(tab)),
+                text(tab_name(tab)).size(FONT_SIZE - 2),
+                tooltip::Position::Bottom,
+            )
+            .into()
+        }))
+        .spacing(2);
+
+        let emojis: Vec<String> = if !self.search.trim().is_empty() {
+            let query = self.search.trim().to_lowercase();
+            emojis::iter()
+                .filter(|emoji| {
+                    emoji.name().contains(&query)
+                        || emoji
+                            .shortcodes()
+                            .any(|shortcode| shortcode.contains(&query))
+                })
+                .take(MAX_SEARCH_RESULTS)
+                .map(|emoji| self.with_skin_tone(emoji))
+                .collect()
+        } else {
+            match self.tab {
+                Tab::Recent => self.recent.clone(),
+                Tab::Group(group) => group
+                    .emojis()
+                    .map(|emoji| self.with_skin_tone(emoji))
+                    .collect(),
+            }
+        };
+
+        let grid: Element<Message> = if emojis.is_empty() {
+            text(if self.search.is_empty() {
+                "Nothing here yet"
+            } else {
+                "No emoji found"
+            })
+            .size(FONT_SIZE)
+            .into()
+        } else {
+            Row::with_children(emojis.into_iter().map(|emoji| {
+                button(text(emoji.clone()).size(EMOJI_SIZE))
+                    .style(button::text)
+                    .padding(3)
+                    .on_press(Message::Picked(emoji))
+                    .into()
+            }))
+            .wrap()
+            .into()
+        };
+
+        column![
+            row![search, skin_tones]
+                .spacing(10)
+                .align_y(Alignment::Center),
+            tabs,
+            rule::horizontal(1),
+            scrollable(grid).height(Length::Fill)
+        ]
+        .spacing(10)
+        .width(WIDTH)
+        .height(HEIGHT)
+        .into()
+    }
+
+    fn with_skin_tone(&self, emoji: &'static Emoji) -> String {
+        emoji
+            .with_skin_tone(self.skin_tone)
+            .unwrap_or(emoji)
+            .as_str()
+            .to_string()
+    }
+}
+
+fn tab_name(tab: Tab) -> &'static str {
+    match tab {
+        Tab::Recent => "Recently used",
+        Tab::Group(Group::SmileysAndEmotion) => "Smileys & emotion",
+        Tab::Group(Group::PeopleAndBody) => "People & body",
+        Tab::Group(Group::AnimalsAndNature) => "Animals & nature",
+        Tab::Group(Group::FoodAndDrink) => "Food & drink",
+        Tab::Group(Group::TravelAndPlaces) => "Travel & places",
+        Tab::Group(Group::Activities) => "Activities",
+        Tab::Group(Group::Objects) => "Objects",
+        Tab::Group(Group::Symbols) => "Symbols",
+        Tab::Group(Group::Flags) => "Flags",
+    }
+}
@@ -0,0 +1,448 @@
+// Sets up cross-signing and secret storage for accounts that have neither,
+// and shows the recovery key that unlocks them
+use crate::chat::password_auth;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::clipboard;
+use iced::widget::button;
+use iced::widget::checkbox;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::radio;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 460.0;
+const MIN_PASSPHRASE_LENGTH: usize = 10;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Generated,
+    Passphrase,
+}
+
+enum Stage {
+    // Confirming the account password to upload the cross-signing keys
+    CrossSigning,
+    // Choosing between a generated key and a passphrase
+    SecretStorage,
+    ShowKey(String),
+}
+
+pub struct EncryptionSetup {
+    client: Client,
+    stage: Stage,
+    password: String,
+    key_kind: KeyKind,
+    passphrase: String,
+    confirmation: String,
+    stored: bool,
+    saved_to: Option<PathBuf>,
+    running: bool,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    PasswordInput(String),
+    Bootstrap,
+    Bootstrapped(Result<(), String>),
+    KeyKindSelected(KeyKind),
+    PassphraseInput(String),
+    ConfirmationInput(String),
+    CreateKey,
+    KeyCreated(Result<String, String>),
+    Copy,
+    Save,
+    Saved(Result<Option<PathBuf>, String>),
+    ToggleStored(bool),
+    Close,
+}
+
+impl EncryptionSetup {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            stage: Stage::CrossSigning,
+            password: String::new(),
+            key_kind: KeyKind::Generated,
+            passphrase: String::new(),
+            confirmation: String::new(),
+            stored: false,
+            saved_to: None,
+            running: false,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::PasswordInput(password) => self.password = password,
+            // Enter submits even while the buttons are disabled
+            Message::Bootstrap => {
+                if self.running {
+                    return Action::None;
+                }
+                self.running = true;
+                self.error = None;
+                return Action::Task(Task::perform(
+                    bootstrap(self.client.clone(), self.password.clone()),
+                    Message::Bootstrapped,
+                ));
+            }
+            Message::Bootstrapped(result) => {
+                self.running = false;
+                match result {
+                    Ok(()) => {
+                        self.password.clear();
+                        self.stage = Stage::SecretStorage;
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::KeyKindSelected(key_kind) => self.key_kind = key_kind,
+            Message::PassphraseInput(passphrase) => {
+                self.passphrase = passphrase;
+            }
+            Message::ConfirmationInput(confirmation) => {
+                self.confirmation = confirmation;
+            }
+            Message::CreateKey => {
+                // A second key would replace the one about to be shown
+                if self.running {
+                    return Action::None;
+                }
+                let passphrase = match self.key_kind {
+                    KeyKind::Generated => None,
+                    KeyKind::Passphrase => match self.check_passphrase() {
+                        Ok(()) => Some(self.passphrase.clone()),
+                        Err(error) => {
+                            self.error = Some(error);
+                            return Action::None;
+                        }
+                    },
+                };
+                self.running = true;
+                self.error = None;
+                return Action::Task(Task::perform(
+                    create_secret_store(self.client.clone(), passphrase),
+                    Message::KeyCreated,
+                ));
+            }
+            Message::KeyCreated(result) => {
+                self.running = false;
+                match result {
+                    Ok(key) => {
+                        self.passphrase.clear();
+                        self.confirmation.clear();
+                        self.stage = Stage::ShowKey(key);
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Copy => {
+                if let Stage::ShowKey(key) = &self.stage {
+                    return Action::Task(clipboard::write(key.clone()));
+                }
+            }
+            Message::Save => {
+                if let Stage::ShowKey(key) = &self.stage {
+                    return Action::Task(Task::perform(
+                        save_key(key.clone()),
+                        Message::Saved,
+                    ));
+                }
+            }
+            Message::Saved(result) => match result {
+                Ok(Some(path)) => self.saved_to = Some(path),
+                Ok(None) => (),
+                Err(error) => self.error = Some(error),
+            },
+            Message::ToggleStored(stored) => self.stored = stored,
+            Message::Close => {
+                if self.can_close() {
+                    return Action::Close;
+                }
+            }
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut content = column![
+            text("Set up secure messaging").size(20),
+            rule::horizontal(1)
+        ]
+        .spacing(10)
+        .width(WIDTH);
+
+        let (body, actions): (Element<Message>, _) = match &self.stage {
+            Stage::CrossSigning => (
+                column![
+                    text(
+                        "Your account doesn't have cross-signing yet. It lets \
+                         you verify your sessions and other people, so they \
+                         can trust your messages."
+                    )
+                    .size(FONT_SIZE),
+                    text_input("Account password", &self.password)
+                        .secure(true)
+                        .on_input(Message::PasswordInput)
+                        .on_submit(Message::Bootstrap)
+                        .size(FONT_SIZE),
+                ]
+                .spacing(10)
+                .into(),
+                vec![
+                    ("Later", Some(Message::Close), false),
+                    (
+                        "Continue",
+                        (!self.running).then_some(Message::Bootstrap),
+                        true,
+                    ),
+                ],
+            ),
+            Stage::SecretStorage => {
+                let mut body = column![
+                    text(
+                        "Your keys are stored on the server, encrypted with a \
+                         key only you have. You'll need it to read your \
+                         messages when signing in on a new device."
+                    )
+                    .size(FONT_SIZE),
+                    radio(
+                        "Generate a recovery key",
+                        KeyKind::Generated,
+                        Some(self.key_kind),
+                        Message::KeyKindSelected
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE),
+                    radio(
+                        "Use a passphrase",
+                        KeyKind::Passphrase,
+                        Some(self.key_kind),
+                        Message::KeyKindSelected
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE),
+                ]
+                .spacing(10);
+                if self.key_kind == KeyKind::Passphrase {
+                    body = body
+                        .push(
+                            text_input("Passphrase", &self.passphrase)
+                                .secure(true)
+                                .on_input(Message::PassphraseInput)
+                                .size(FONT_SIZE),
+                        )
+                        .push(
+                            text_input(
+                                "Repeat the passphrase",
+                                &self.confirmation,
+                            )
+                            .secure(true)
+                            .on_input(Message::ConfirmationInput)
+                            .on_submit(Message::CreateKey)
+                            .size(FONT_SIZE),
+                        );
+                }
+                (
+                    body.into(),
+                    vec![
+                        ("Later", Some(Message::Close), false),
+                        (
+                            "Continue",
+                            (!self.running).then_some(Message::CreateKey),
+                            true,
+                        ),
+                    ],
+                )
+            }
+            Stage::ShowKey(key) => {
+                let mut body = column![
+                    text(
+                        "This is your recovery key. Store it somewhere safe, \
+                         like a password manager. Anyone with it can read \
+                         your encrypted messages."
+                    )
+                    .size(FONT_SIZE),
+                    container(
+                        text(key)
+                            .size(FONT_SIZE + 2)
+                            .font(iced::Font::MONOSPACE)
+                    )
+                    .padding(10)
+                    .width(Length::Fill)
+                    .style(container::rounded_box),
+                    row![
+                        button(text("Copy").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press(Message::Copy),
+                        button(text("Save to file").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press(Message::Save)
+                    ]
+                    .spacing(10),
+                ]
+                .spacing(10);
+                if self.key_kind == KeyKind::Passphrase {
+                    body = body.push(
+                        text("Your passphrase can be used instead of the key.")
+                            .size(FONT_SIZE),
+                    );
+                }
+                if let Some(path) = &self.saved_to {
+                    body = body.push(
+                        text(format!("Saved to {}", path.display()))
+                            .size(FONT_SIZE),
+                    );
+                }
+                body = body.push(
+                    checkbox(self.stored)
+                        .label("I have stored my recovery key")
+                        .on_toggle(Message::ToggleStored)
+                        .size(14)
+                        .text_size(FONT_SIZE),
+                );
+                (
+                    body.into(),
+                    vec![("Done", self.stored.then_some(Message::Close), true)],
+                )
+            }
+        };
+        content = content.push(body);
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        let mut buttons = row![text("").width(Length::Fill)]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        if self.running {
+            buttons = buttons.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        for (label, message, is_primary) in actions {
+            buttons = buttons.push(
+                button(text(label).size(FONT_SIZE))
+                    .style(if is_primary {
+                        button::primary
+                    } else {
+                        button::secondary
+                    })
+                    .on_press_maybe(message),
+            );
+        }
+
+        content.push(buttons).into()
+    }
+
+    /// Whether the dialog can be dismissed without losing the recovery key.
+    fn can_close(&self) -> bool {
+        !matches!(self.stage, Stage::ShowKey(_)) || self.stored
+    }
+
+    fn check_passphrase(&self) -> Result<(), String> {
+        if self.passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+            return Err(format!(
+                "Use at least {MIN_PASSPHRASE_LENGTH} characters"
+            ));
+        }
+        if self.passphrase != self.confirmation {
+            return Err(String::from("The passphrases don't match"));
+        }
+        Ok(())
+    }
+}
+
+/// Whether the account has no cross-signing identity at all, so setting one
+/// up won't replace keys other sessions rely on.
+pub async fn is_needed(client: Client) -> bool {
+    let Some(user_id) = client.user_id().map(ToOwned::to_owned) else {
+        return false;
+    };
+    matches!(
+        client.encryption().request_user_identity(&user_id).await,
+        Ok(None)
+    )
+}
+
+/// Creates and uploads the cross-signing keys. Servers ask for the password
+/// first, which the second attempt provides.
+async fn bootstrap(client: Client, password: String) -> Result<(), String> {
+    let encryption = client.encryption();
+    let Err(error) = encryption.bootstrap_cross_signing(None).await else {
+        return Ok(());
+    };
+    let (Some(info), Some(user_id)) =
+        (error.as_uiaa_response(), client.user_id())
+    else {
+        return Err(error.to_string());
+    };
+
+    let auth = password_auth(user_id, info.session.clone(), password);
+    encryption
+        .bootstrap_cross_signing(Some(auth))
+        .await
+        .map_err(|error| match error.as_uiaa_response() {
+            Some(_) => String::from("Wrong password"),
+            None => error.to_string(),
+        })
+}
+
+/// Creates secret storage holding the cross-signing keys, and returns its
+/// recovery key.
+async fn create_secret_store(
+    client: Client,
+    passphrase: Option<String>,
+) -> Result<String, String> {
+    let secret_storage = client.encryption().secret_storage();
+    let create = secret_storage.create_secret_store();
+    let store = match &passphrase {
+        Some(passphrase) => create.with_passphrase(passphrase).await,
+        None => create.await,
+    }
+    .map_err(|error| error.to_string())?;
+    Ok(store.secret_storage_key())
+}
+
+/// Asks where to save the recovery key. Returns `None` when the user cancels.
+async fn save_key(key: String) -> Result<Option<PathBuf>, String> {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .set_file_name("recovery-key.txt")
+        .save_file()
+        .await
+    else {
+        return Ok(None);
+    };
+
+    let path = file.path().to_owned();
+    tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || fs::write(path, key)
+    })
+    .await
+    .map_err(|error| error.to_string())?
+    .map_err(|error| error.to_string())?;
+    Ok(Some(path))
+}
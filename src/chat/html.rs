@@ -0,0 +1,595 @@
+// Turns `org.matrix.custom.html` formatted bodies into iced rich text.
+// The HTML is sanitized against the elements and attributes the Matrix spec
+// allows before anything is rendered.
+use iced::Element;
+use iced::Font;
+use iced::font;
+use iced::widget::Column;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::rich_text;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::span;
+use iced::widget::text;
+use iced::widget::text::Span;
+use matrix_sdk::ruma::OwnedRoomOrAliasId;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::matrix_uri::MatrixId;
+use ruma_html::Html;
+use ruma_html::NodeData;
+use ruma_html::NodeRef;
+use ruma_html::SanitizerConfig;
+use ruma_html::matrix::AnchorUri;
+use ruma_html::matrix::MatrixElement;
+
+const FONT_SIZE: u32 = 13;
+const BLOCK_SPACING: u32 = 6;
+const INDENT: u32 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Link {
+    Url(String),
+    User(OwnedUserId),
+    Room(OwnedRoomOrAliasId),
+}
+
+impl Link {
+    /// The address to open in the browser when the link is clicked. Pills are
+    /// turned back into matrix.to links.
+    pub fn url(&self) -> String {
+        match self {
+            Link::Url(url) => url.clone(),
+            Link::User(user_id) => user_id.matrix_to_uri().to_string(),
+            Link::Room(room) => format!("https://matrix.to/#/{room}"),
+        }
+    }
+
+    /// What a pill shows when its anchor has no text of its own.
+    fn pill_label(&self) -> String {
+        match self {
+            Link::Url(url) => url.clone(),
+            Link::User(user_id) => user_id.to_string(),
+            Link::Room(room) => room.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Heading(u8, Vec<Inline>),
+    Code(String),
+    Quote(Vec<Block>),
+    /// `start` is `None` for unordered lists.
+    List {
+        start: Option<i64>,
+        items: Vec<Vec<Block>>,
+    },
+    Rule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inline {
+    pub text: String,
+    pub style: InlineStyle,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InlineStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub strikethrough: bool,
+    pub underline: bool,
+    pub code: bool,
+    pub link: Option<Link>,
+}
+
+/// Parses and sanitizes a formatted body. Returns `None` when nothing
+/// renderable is left, so the caller can fall back to the plain `body`.
+pub fn parse(formatted_body: &str) -> Option<Vec<Block>> {
+    let html = Html::parse(formatted_body);
+    html.sanitize_with(&SanitizerConfig::strict().remove_reply_fallback());
+
+    let mut blocks = Vec::new();
+    let mut line = Vec::new();
+    collect(
+        html.children(),
+        &InlineStyle::default(),
+        &mut blocks,
+        &mut line,
+    );
+    flush(&mut line, &mut blocks);
+
+    if blocks.is_empty() {
+        None
+    } else {
+        Some(blocks)
+    }
+}
+
+fn collect(
+    nodes: impl Iterator<Item = NodeRef>,
+    style: &InlineStyle,
+    blocks: &mut Vec<Block>,
+    line: &mut Vec<Inline>,
+) {
+    for node in nodes {
+        let element = match node.data() {
+            NodeData::Text(text) => {
+                push_text(line, &text.borrow(), style);
+                continue;
+            }
+            NodeData::Element(element) => element.to_matrix().element,
+            _ => continue,
+        };
+
+        match element {
+            MatrixElement::B | MatrixElement::Strong => {
+                let style = InlineStyle {
+                    bold: true,
+                    ..style.clone()
+                };
+                collect(node.children(), &style, blocks, line);
+            }
+            MatrixElement::I | MatrixElement::Em => {
+                let style = InlineStyle {
+                    italic: true,
+                    ..style.clone()
+                };
+                collect(node.children(), &style, blocks, line);
+            }
+            MatrixElement::S | MatrixElement::Del => {
+                let style = InlineStyle {
+                    strikethrough: true,
+                    ..style.clone()
+                };
+                collect(node.children(), &style, blocks, line);
+            }
+            MatrixElement::U => {
+                let style = InlineStyle {
+                    underline: true,
+                    ..style.clone()
+                };
+                collect(node.children(), &style, blocks, line);
+            }
+            MatrixElement::Code(_) => {
+                line.push(Inline {
+                    text: text_content(&node),
+                    style: InlineStyle {
+                        code: true,
+                        ..style.clone()
+                    },
+                });
+            }
+            MatrixElement::A(anchor) => {
+                match anchor.href.and_then(|href| link(&href)) {
+                    // Mentions and room links become a single pill, labelled
+                    // with the display name the sender put in the anchor
+                    Some(pill @ (Link::User(_) | Link::Room(_))) => {
+                        let label = text_content(&node).trim().to_string();
+                        line.push(Inline {
+                            text: if label.is_empty() {
+                                pill.pill_label()
+                            } else {
+                                label
+                            },
+                            style: InlineStyle {
+                                link: Some(pill),
+                                ..style.clone()
+                            },
+                        });
+                    }
+                    target => {
+                        let style = InlineStyle {
+                            link: target.or(style.link.clone()),
+                            ..style.clone()
+                        };
+                        collect(node.children(), &style, blocks, line);
+                    }
+                }
+            }
+            MatrixElement::Br => line.push(Inline {
+                text: String::from("\n"),
+                style: style.clone(),
+            }),
+            MatrixElement::H(heading) => {
+                flush(line, blocks);
+                let level = heading.level.value();
+                let mut inner_blocks = Vec::new();
+                let mut inner = Vec::new();
+                collect(node.children(), style, &mut inner_blocks, &mut inner);
+                flush(&mut inner, &mut inner_blocks);
+                // Paragraphs inside a heading are still part of it
+                blocks.extend(inner_blocks.into_iter().map(
+                    |block| match block {
+                        Block::Paragraph(inlines) => {
+                            Block::Heading(level, inlines)
+                        }
+                        block => block,
+                    },
+                ));
+            }
+            MatrixElement::Pre => {
+                flush(line, blocks);
+                let code = text_content(&node);
+                blocks.push(Block::Code(
+                    code.strip_suffix('\n').unwrap_or(&code).to_string(),
+                ));
+            }
+            MatrixElement::Blockquote => {
+                flush(line, blocks);
+                blocks.push(Block::Quote(child_blocks(&node, style)));
+            }
+            MatrixElement::Ul | MatrixElement::Ol(_) => {
+                flush(line, blocks);
+                let start = match element {
+                    MatrixElement::Ol(list) => Some(list.start.unwrap_or(1)),
+                    _ => None,
+                };
+                let items = node
+                    .children()
+                    .filter(|child| child.as_element().is_some())
+                    .map(|child| child_blocks(&child, style))
+                    .collect();
+                blocks.push(Block::List { start, items });
+            }
+            MatrixElement::Hr => {
+                flush(line, blocks);
+                blocks.push(Block::Rule);
+            }
+            MatrixElement::P
+            | MatrixElement::Div(_)
+            | MatrixElement::Li
+            | MatrixElement::Table
+            | MatrixElement::Tr
+            | MatrixElement::Details => {
+                flush(line, blocks);
+                collect(node.children(), style, blocks, line);
+                flush(line, blocks);
+            }
+            MatrixElement::MatrixReply => (),
+            _ => collect(node.children(), style, blocks, line),
+        }
+    }
+}
+
+fn child_blocks(node: &NodeRef, style: &InlineStyle) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut line = Vec::new();
+    collect(node.children(), style, &mut blocks, &mut line);
+    flush(&mut line, &mut blocks);
+    blocks
+}
+
+/// Collapses whitespace the way a browser would outside of `<pre>`.
+fn push_text(line: &mut Vec<Inline>, text: &str, style: &InlineStyle) {
+    let ends_in_space = line
+        .last()
+        .is_none_or(|inline| inline.text.ends_with([' ', '\n']));
+
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = ends_in_space;
+    for char in text.chars() {
+        if char.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(char);
+            in_space = false;
+        }
+    }
+
+    if !collapsed.is_empty() {
+        line.push(Inline {
+            text: collapsed,
+            style: style.clone(),
+        });
+    }
+}
+
+fn trim(line: &mut Vec<Inline>) {
+    if let Some(last) = line.last_mut() {
+        last.text.truncate(last.text.trim_end().len());
+    }
+    line.retain(|inline| !inline.text.is_empty());
+}
+
+fn flush(line: &mut Vec<Inline>, blocks: &mut Vec<Block>) {
+    trim(line);
+    if !line.is_empty() {
+        blocks.push(Block::Paragraph(std::mem::take(line)));
+    }
+}
+
+fn text_content(node: &NodeRef) -> String {
+    let mut content = String::new();
+    for child in node.children() {
+        match child.data() {
+            NodeData::Text(text) => content.push_str(&text.borrow()),
+            NodeData::Element(_) => content.push_str(&text_content(&child)),
+            _ => (),
+        }
+    }
+    content
+}
+
+/// Where an anchor leads. `None` for hrefs we don't know how to open, whose
+/// text is then shown as is.
+fn link(href: &AnchorUri) -> Option<Link> {
+    let (id, url) = match href {
+        AnchorUri::Matrix(uri) => (uri.id(), uri.to_string()),
+        AnchorUri::MatrixTo(uri) => (uri.id(), uri.to_string()),
+        AnchorUri::Other(uri) => return Some(Link::Url(uri.to_string())),
+        _ => return None,
+    };
+    Some(match id {
+        MatrixId::User(user_id) => Link::User(user_id.clone()),
+        MatrixId::Room(room_id) => Link::Room(room_id.clone().into()),
+        MatrixId::RoomAlias(alias) => Link::Room(alias.clone().into()),
+        _ => Link::Url(url),
+    })
+}
+
+pub fn view<'a, Message: 'a>(
+    blocks: Vec<Block>,
+    on_link: fn(Link) -> Message,
+) -> Element<'a, Message> {
+    Column::with_children(
+        blocks.into_iter().map(|block| view_block(block, on_link)),
+    )
+    .spacing(BLOCK_SPACING)
+    .into()
+}
+
+fn view_block<'a, Message: 'a>(
+    block: Block,
+    on_link: fn(Link) -> Message,
+) -> Element<'a, Message> {
+    match block {
+        Block::Paragraph(inlines) => view_inlines(inlines, FONT_SIZE, on_link),
+        Block::Heading(level, inlines) => {
+            let size = match level {
+                1 => 22,
+                2 => 19,
+                3 => 17,
+                4 => 15,
+                _ => FONT_SIZE,
+            };
+            let inlines = inlines
+                .into_iter()
+                .map(|inline| Inline {
+                    style: InlineStyle {
+                        bold: true,
+                        ..inline.style
+                    },
+                    ..inline
+                })
+                .collect();
+            view_inlines(inlines, size, on_link)
+        }
+        Block::Code(code) => {
+            container(text(code).size(FONT_SIZE).font(Font::MONOSPACE))
+                .padding(6)
+                .style(container::rounded_box)
+                .into()
+        }
+        Block::Quote(blocks) => row![
+            rule::vertical(3),
+            container(view(blocks, on_link)).padding([0, 6])
+        ]
+        .height(iced::Length::Shrink)
+        .into(),
+        Block::List { start, items } => Column::with_children(
+            items.into_iter().enumerate().map(|(index, item)| {
+                let marker = match start {
+                    Some(start) => {
+                        format!("{}.", start.saturating_add(index as i64))
+                    }
+                    None => String::from("•"),
+                };
+                row![
+                    text(marker).size(FONT_SIZE).width(INDENT),
+                    view(item, on_link)
+                ]
+                .into()
+            }),
+        )
+        .spacing(BLOCK_SPACING / 2)
+        .into(),
+        Block::Rule => column![rule::horizontal(1)].into(),
+    }
+}
+
+fn view_inlines<'a, Message: 'a>(
+    inlines: Vec<Inline>,
+    size: u32,
+    on_link: fn(Link) -> Message,
+) -> Element<'a, Message> {
+    let spans: Vec<Span<'a, Link>> = inlines
+        .into_iter()
+        .map(|inline| view_inline(inline, size))
+        .collect();
+
+    rich_text(spans).size(size).on_link_click(on_link).into()
+}
+
+fn view_inline<'a>(inline: Inline, size: u32) -> Span<'a, Link> {
+    let style = inline.style;
+    let link = style.link;
+
+    let font = Font {
+        weight: if style.bold {
+            font::Weight::Bold
+        } else {
+            font::Weight::Normal
+        },
+        style: if style.italic {
+            font::Style::Italic
+        } else {
+            font::Style::Normal
+        },
+        ..if style.code {
+            Font::MONOSPACE
+        } else {
+            Font::DEFAULT
+        }
+    };
+
+    let mut span = span(inline.text)
+        .size(size)
+        .font(font)
+        .strikethrough(style.strikethrough)
+        .underline(style.underline);
+
+    if style.code {
+        span = span
+            .background(iced::Color::from_rgba(1.0, 1.0, 1.0, 0.08))
+            .padding([0, 2]);
+    }
+
+    match link {
+        Some(link @ (Link::User(_) | Link::Room(_))) => span
+            .link(link)
+            .color(iced::Color::WHITE)
+            .background(iced::Color::from_rgb(0.25, 0.35, 0.6))
+            .border(iced::border::rounded(8))
+            .padding([0, 4]),
+        Some(link) => span
+            .link(link)
+            .color(iced::Color::from_rgb(0.45, 0.65, 1.0))
+            .underline(true),
+        None => span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Inline {
+        Inline {
+            text: text.to_string(),
+            style: InlineStyle::default(),
+        }
+    }
+
+    fn bold(text: &str) -> Inline {
+        Inline {
+            text: text.to_string(),
+            style: InlineStyle {
+                bold: true,
+                ..InlineStyle::default()
+            },
+        }
+    }
+
+    #[test]
+    fn heading() {
+        assert_eq!(
+            parse("<h2>Title</h2>text"),
+            Some(vec![
+                Block::Heading(2, vec![plain("Title")]),
+                Block::Paragraph(vec![plain("text")]),
+            ])
+        );
+    }
+
+    #[test]
+    fn heading_keeps_block_content() {
+        assert_eq!(
+            parse("<h1><b>first</b><p>second</p>third</h1>"),
+            Some(vec![
+                Block::Heading(1, vec![bold("first")]),
+                Block::Heading(1, vec![plain("second")]),
+                Block::Heading(1, vec![plain("third")]),
+            ])
+        );
+    }
+
+    #[test]
+    fn ordered_list_start() {
+        assert_eq!(
+            parse("<ol start=\"3\"><li>a</li><li>b</li></ol>"),
+            Some(vec![Block::List {
+                start: Some(3),
+                items: vec![
+                    vec![Block::Paragraph(vec![plain("a")])],
+                    vec![Block::Paragraph(vec![plain("b")])],
+                ],
+            }])
+        );
+        assert_eq!(
+            parse("<ul><li>a</li></ul>"),
+            Some(vec![Block::List {
+                start: None,
+                items: vec![vec![Block::Paragraph(vec![plain("a")])]],
+            }])
+        );
+    }
+
+    #[test]
+    fn mention_is_one_pill() {
+        let user_id = OwnedUserId::try_from("@alice:example.org").unwrap();
+        assert_eq!(
+            parse(
+                "Hi <a href=\"https://matrix.to/#/@alice:example.org\">\
+                 <b>Alice</b> Liddell</a>!"
+            ),
+            Some(vec![Block::Paragraph(vec![
+                plain("Hi "),
+                Inline {
+                    text: String::from("Alice Liddell"),
+                    style: InlineStyle {
+                        link: Some(Link::User(user_id)),
+                        ..InlineStyle::default()
+                    },
+                },
+                plain("!"),
+            ])])
+        );
+    }
+
+    #[test]
+    fn empty_mention_shows_the_user_id() {
+        let user_id = OwnedUserId::try_from("@bob:example.org").unwrap();
+        assert_eq!(
+            parse("<a href=\"https://matrix.to/#/@bob:example.org\"></a>"),
+            Some(vec![Block::Paragraph(vec![Inline {
+                text: String::from("@bob:example.org"),
+                style: InlineStyle {
+                    link: Some(Link::User(user_id)),
+                    ..InlineStyle::default()
+                },
+            }])])
+        );
+    }
+
+    #[test]
+    fn web_link() {
+        assert_eq!(
+            parse("<a href=\"https://example.org\">site</a>"),
+            Some(vec![Block::Paragraph(vec![Inline {
+                text: String::from("site"),
+                style: InlineStyle {
+                    link: Some(Link::Url(String::from("https://example.org"))),
+                    ..InlineStyle::default()
+                },
+            }])])
+        );
+    }
+
+    #[test]
+    fn unknown_link_is_plain_text() {
+        assert_eq!(
+            parse("<a href=\"javascript:alert(1)\">click</a>"),
+            Some(vec![Block::Paragraph(vec![plain("click")])])
+        );
+        assert_eq!(
+            parse("<a href=\"gopher://example.org\">hole</a>"),
+            Some(vec![Block::Paragraph(vec![plain("hole")])])
+        );
+    }
+}
@@ -0,0 +1,277 @@
+// Rooms the user has been invited to, and the preview shown when one is
+// selected in the room list
+use crate::chat::avatar;
+use crate::chat::media::MediaCache;
+use crate::chat::room_name;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center;
+use iced::widget::column;
+use iced::widget::image;
+use iced::widget::row;
+use iced::widget::text;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::ruma::OwnedRoomId;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::RoomId;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const AVATAR_SIZE: f32 = 28.0;
+const PREVIEW_AVATAR_SIZE: f32 = 72.0;
+const PREVIEW_WIDTH: f32 = 400.0;
+
+#[derive(Debug, Clone)]
+pub struct Inviter {
+    pub user_id: OwnedUserId,
+    pub name: String,
+}
+
+#[derive(Clone)]
+pub struct Invite {
+    pub room: Room,
+    pub name: String,
+    pub inviter: Option<Inviter>,
+    pub avatar: Option<image::Handle>,
+}
+
+impl Invite {
+    /// A room list entry showing who sent the invite.
+    pub fn view<'a, Message: Clone + 'a>(
+        &'a self,
+        is_selected: bool,
+        on_press: Message,
+    ) -> Element<'a, Message> {
+        let mut details = column![text(&self.name).size(FONT_SIZE)];
+        if let Some(inviter) = &self.inviter {
+            details = details.push(
+                text(format!("Invited by {}", inviter.name))
+                    .size(FONT_SIZE - 3),
+            );
+        }
+
+        button(
+            row![self.picture(AVATAR_SIZE), details]
+                .spacing(8)
+                .align_y(Alignment::Center),
+        )
+        .width(iced::Length::Fill)
+        .style(if is_selected {
+            button::primary
+        } else {
+            button::text
+        })
+        .on_press(on_press)
+        .into()
+    }
+
+    fn picture<'a, Message: 'a>(&self, size: f32) -> Element<'a, Message> {
+        match &self.avatar {
+            Some(handle) => avatar::picture(handle, size),
+            None => {
+                avatar::view(self.room.room_id().as_str(), &self.name, size)
+            }
+        }
+    }
+}
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Joined(Room),
+    Declined(OwnedRoomId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Response {
+    Accept,
+    Decline,
+    DeclineAndIgnore,
+}
+
+/// Shown in place of the timeline until the invite is accepted or declined.
+pub struct InvitePreview {
+    invite: Invite,
+    // The response being sent
+    responding: Option<Response>,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Accept,
+    Decline,
+    DeclineAndIgnore,
+    Responded(Result<(), String>),
+}
+
+impl InvitePreview {
+    pub fn new(invite: Invite) -> Self {
+        Self {
+            invite,
+            responding: None,
+            error: None,
+        }
+    }
+
+    pub fn room_id(&self) -> &RoomId {
+        self.invite.room.room_id()
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        let response = match message {
+            Message::Accept => Response::Accept,
+            Message::Decline => Response::Decline,
+            Message::DeclineAndIgnore => Response::DeclineAndIgnore,
+            Message::Responded(result) => {
+                let Some(response) = self.responding.take() else {
+                    return Action::None;
+                };
+                return match result {
+                    Ok(()) if response == Response::Accept => {
+                        Action::Joined(self.invite.room.clone())
+                    }
+                    Ok(()) => Action::Declined(self.room_id().to_owned()),
+                    Err(error) => {
+                        self.error = Some(error);
+                        Action::None
+                    }
+                };
+            }
+        };
+        if self.responding.is_some() {
+            return Action::None;
+        }
+
+        self.responding = Some(response);
+        self.error = None;
+        let room = self.invite.room.clone();
+        let ignore = match response {
+            Response::DeclineAndIgnore => self
+                .invite
+                .inviter
+                .as_ref()
+                .map(|inviter| inviter.user_id.clone()),
+            _ => None,
+        };
+        Action::Task(Task::perform(
+            respond(room, response, ignore),
+            Message::Responded,
+        ))
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let invite = &self.invite;
+
+        let mut items: Vec<Element<Message>> = vec![
+            invite.picture(PREVIEW_AVATAR_SIZE),
+            text(&invite.name).size(20).into(),
+        ];
+        if let Some(topic) = invite.room.topic() {
+            items.push(text(topic).size(FONT_SIZE).into());
+        }
+        items.push(
+            text(match &invite.inviter {
+                Some(inviter) => {
+                    format!(
+                        "{} ({}) invited you",
+                        inviter.name, inviter.user_id
+                    )
+                }
+                None => String::from("You were invited to this room"),
+            })
+            .size(FONT_SIZE)
+            .into(),
+        );
+
+        let can_respond = self.responding.is_none();
+        let mut buttons = row![
+            button(text("Accept").size(FONT_SIZE))
+                .on_press_maybe(can_respond.then_some(Message::Accept)),
+            button(text("Decline").size(FONT_SIZE))
+                .style(button::secondary)
+                .on_press_maybe(can_respond.then_some(Message::Decline)),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        if invite.inviter.is_some() {
+            buttons = buttons.push(
+                button(text("Decline and ignore user").size(FONT_SIZE))
+                    .style(button::danger)
+                    .on_press_maybe(
+                        can_respond.then_some(Message::DeclineAndIgnore),
+                    ),
+            );
+        }
+        if self.responding.is_some() {
+            buttons = buttons.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        items.push(buttons.into());
+
+        if let Some(error) = &self.error {
+            items.push(text(error).size(FONT_SIZE).into());
+        }
+
+        center(
+            Column::with_children(items)
+                .spacing(12)
+                .max_width(PREVIEW_WIDTH)
+                .align_x(Alignment::Center),
+        )
+        .into()
+    }
+}
+
+/// Details for every room the user is invited to.
+pub async fn load(client: Client, media: MediaCache) -> Vec<Invite> {
+    let mut invites = Vec::new();
+    for room in client.invited_rooms() {
+        let inviter = match room.invite_details().await {
+            Ok(details) => details.inviter.map(|member| Inviter {
+                user_id: member.user_id().to_owned(),
+                name: member.name().to_string(),
+            }),
+            Err(_error) => None,
+        };
+        let avatar = match room.avatar_url() {
+            Some(uri) => media.clone().avatar(client.clone(), uri).await.ok(),
+            None => None,
+        };
+        invites.push(Invite {
+            name: room_name(&room),
+            room,
+            inviter,
+            avatar,
+        });
+    }
+    invites.sort_by(|a, b| a.name.cmp(&b.name));
+    invites
+}
+
+async fn respond(
+    room: Room,
+    response: Response,
+    ignore: Option<OwnedUserId>,
+) -> Result<(), String> {
+    match response {
+        Response::Accept => room.join().await,
+        Response::Decline | Response::DeclineAndIgnore => room.leave().await,
+    }
+    .map_err(|error| error.to_string())?;
+
+    if let Some(user_id) = ignore {
+        room.client()
+            .account()
+            .ignore_user(&user_id)
+            .await
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
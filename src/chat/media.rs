@@ -0,0 +1,298 @@
+// Downloads media from the homeserver, keeping a copy of unencrypted media on
+// disk and of decrypted media in memory
+use crate::APP_NAME;
+use iced::widget::image;
+use matrix_sdk::Client;
+use matrix_sdk::media::MediaFormat;
+use matrix_sdk::media::MediaRequestParameters;
+use matrix_sdk::media::MediaThumbnailSettings;
+use matrix_sdk::media::UniqueKey;
+use matrix_sdk::ruma::OwnedMxcUri;
+use matrix_sdk::ruma::UInt;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::api::client::media::get_content_thumbnail::v3::Method;
+use matrix_sdk::ruma::events::room::MediaSource;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::SystemTime;
+
+const MAX_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+const MAX_MEMORY_SIZE: usize = 64 * 1024 * 1024;
+pub const THUMBNAIL_SIZE: u32 = 300;
+const AVATAR_SIZE: u32 = 96;
+
+/// Unencrypted media files stored in the account's own cache directory. Once
+/// the directory grows over its size limit the least recently used files are
+/// deleted.
+///
+/// Media from encrypted rooms never touches the disk decrypted, it's only kept
+/// in memory for as long as the chat screen is open.
+#[derive(Clone)]
+pub struct MediaCache {
+    dir: PathBuf,
+    max_size: u64,
+    decrypted: Arc<Mutex<Decrypted>>,
+}
+
+/// Decrypted media, least recently used first.
+#[derive(Default)]
+struct Decrypted {
+    entries: Vec<(String, Vec<u8>)>,
+    size: usize,
+}
+
+impl MediaCache {
+    pub fn new(client: &Client) -> Self {
+        let account = client.user_id().map(UserId::as_str).unwrap_or_default();
+        Self {
+            dir: dirs::cache_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(APP_NAME)
+                .join("media")
+                .join(escape(account)),
+            max_size: MAX_CACHE_SIZE,
+            decrypted: Arc::default(),
+        }
+    }
+
+    /// The cached copy if there is one, otherwise the media is downloaded
+    /// (and decrypted) then cached.
+    pub async fn get(
+        &self,
+        client: &Client,
+        request: MediaRequestParameters,
+    ) -> Result<Vec<u8>, String> {
+        if let MediaSource::Encrypted(_) = request.source {
+            return self.get_decrypted(client, request).await;
+        }
+
+        let path = self.dir.join(escape(&request.unique_key()));
+        let cached = path.clone();
+        if let Some(data) = blocking(move || read(&cached)).await.flatten() {
+            return Ok(data);
+        }
+
+        let data = client
+            .media()
+            .get_media_content(&request, false)
+            .await
+            .map_err(|error| error.to_string())?;
+
+        // Not being able to cache isn't worth failing the download over
+        let (dir, max_size) = (self.dir.clone(), self.max_size);
+        let copy = data.clone();
+        blocking(move || {
+            if fs::create_dir_all(&dir).is_ok()
+                && fs::write(&path, copy).is_ok()
+            {
+                evict(&dir, max_size);
+            }
+        })
+        .await;
+
+        Ok(data)
+    }
+
+    async fn get_decrypted(
+        &self,
+        client: &Client,
+        request: MediaRequestParameters,
+    ) -> Result<Vec<u8>, String> {
+        let key = request.unique_key();
+        let cached = self.lock().take(&key);
+        if let Some(data) = cached {
+            self.lock().insert(key, data.clone());
+            return Ok(data);
+        }
+
+        let data = client
+            .media()
+            .get_media_content(&request, false)
+            .await
+            .map_err(|error| error.to_string())?;
+        self.lock().insert(key, data.clone());
+
+        Ok(data)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Decrypted> {
+        self.decrypted
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A preview no bigger than [`THUMBNAIL_SIZE`] when the server can make
+    /// one. Encrypted media can't be resized by the server, so `source` is
+    /// downloaded as is.
+    pub async fn thumbnail(
+        self,
+        client: Client,
+        source: MediaSource,
+    ) -> Result<image::Handle, String> {
+        let format = match source {
+            MediaSource::Plain(_) => {
+                MediaFormat::Thumbnail(MediaThumbnailSettings::new(
+                    UInt::from(THUMBNAIL_SIZE),
+                    UInt::from(THUMBNAIL_SIZE),
+                ))
+            }
+            MediaSource::Encrypted(_) => MediaFormat::File,
+        };
+        let data = self
+            .get(&client, MediaRequestParameters { source, format })
+            .await?;
+        Ok(image::Handle::from_bytes(data))
+    }
+
+    /// A small square crop of a user or room avatar.
+    pub async fn avatar(
+        self,
+        client: Client,
+        uri: OwnedMxcUri,
+    ) -> Result<image::Handle, String> {
+        let format =
+            MediaFormat::Thumbnail(MediaThumbnailSettings::with_method(
+                Method::Crop,
+                UInt::from(AVATAR_SIZE),
+                UInt::from(AVATAR_SIZE),
+            ));
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(uri),
+            format,
+        };
+        let data = self.get(&client, request).await?;
+        Ok(image::Handle::from_bytes(data))
+    }
+
+    /// Asks where to save a file, then downloads it there. Returns `None`
+    /// when the user cancels.
+    pub async fn save(
+        self,
+        client: Client,
+        source: MediaSource,
+        file_name: String,
+    ) -> Result<Option<PathBuf>, String> {
+        let Some(file) = rfd::AsyncFileDialog::new()
+            .set_file_name(file_name)
+            .save_file()
+            .await
+        else {
+            return Ok(None);
+        };
+
+        let request = MediaRequestParameters {
+            source,
+            format: MediaFormat::File,
+        };
+        let data = self.get(&client, request).await?;
+        let path = file.path().to_owned();
+        blocking(move || fs::write(&path, data))
+            .await
+            .ok_or_else(|| String::from("Saving the file was interrupted"))?
+            .map_err(|error| error.to_string())?;
+
+        Ok(Some(file.path().to_owned()))
+    }
+}
+
+impl Decrypted {
+    fn take(&mut self, key: &str) -> Option<Vec<u8>> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let (_, data) = self.entries.remove(index);
+        self.size -= data.len();
+        Some(data)
+    }
+
+    /// Adds `data` as the most recently used entry, dropping the least
+    /// recently used ones until everything fits.
+    fn insert(&mut self, key: String, data: Vec<u8>) {
+        self.size += data.len();
+        self.entries.push((key, data));
+        while self.size > MAX_MEMORY_SIZE && self.entries.len() > 1 {
+            let (_, data) = self.entries.remove(0);
+            self.size -= data.len();
+        }
+    }
+}
+
+/// Runs blocking file system work off the async runtime. `None` if it
+/// panicked.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    tokio::task::spawn_blocking(work).await.ok()
+}
+
+/// The file's contents, marking it as just used.
+fn read(path: &Path) -> Option<Vec<u8>> {
+    let data = fs::read(path).ok()?;
+    // The modification time doubles as the time of last use
+    let _ = File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    Some(data)
+}
+
+/// Deletes the least recently used files until the cache fits.
+fn evict(dir: &Path, max_size: u64) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            metadata
+                .is_file()
+                .then(|| (modified, metadata.len(), entry.path()))
+        })
+        .collect();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, size, path) in files {
+        if total <= max_size {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
+
+/// "1.5 MB" and the like.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Percent-encodes anything that isn't safe in a file name.
+fn escape(key: &str) -> String {
+    let mut name = String::new();
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_') {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    name
+}
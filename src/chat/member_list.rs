@@ -0,0 +1,508 @@
+// Side panel listing the members of a room, grouped by role
+use crate::chat::avatar;
+use crate::chat::media::MediaCache;
+use crate::chat::moderation;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Background;
+use iced::Border;
+use iced::Color;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::Theme;
+use iced::futures::SinkExt;
+use iced::futures::Stream;
+use iced::futures::StreamExt;
+use iced::futures::future;
+use iced::futures::stream;
+use iced::task;
+use iced::widget::Column;
+use iced::widget::bottom_right;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::hover;
+use iced::widget::image;
+use iced::widget::right;
+use iced::widget::row;
+use iced::widget::scrollable;
+use iced::widget::stack;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::RoomMemberships;
+use matrix_sdk::room::RoomMember;
+use matrix_sdk::room::RoomMemberRole;
+use matrix_sdk::ruma::OwnedMxcUri;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::events::presence::PresenceEvent;
+use matrix_sdk::ruma::events::room::member::MembershipState;
+use matrix_sdk::ruma::events::room::power_levels::RoomPowerLevels;
+use matrix_sdk::ruma::events::room::power_levels::UserPowerLevel;
+use matrix_sdk::ruma::presence::PresenceState;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const AVATAR_SIZE: f32 = 28.0;
+const PRESENCE_DOT_SIZE: f32 = 9.0;
+// Members shown at first and added by each "Show more"
+const PAGE_SIZE: usize = 100;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Moderate(OwnedUserId, String, moderation::Kind),
+    Verify(OwnedUserId, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    Admins,
+    Moderators,
+    Members,
+    Invited,
+    Banned,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    user_id: OwnedUserId,
+    name: String,
+    avatar_url: Option<OwnedMxcUri>,
+    power_level: UserPowerLevel,
+    group: Group,
+    presence: Option<PresenceState>,
+}
+
+pub struct MemberList {
+    room: Room,
+    media: MediaCache,
+    // None until the first batch of members is loaded
+    members: Option<Vec<Member>>,
+    // None while loading or when the avatar couldn't be loaded
+    avatars: HashMap<OwnedMxcUri, Option<image::Handle>>,
+    // None until loaded, which hides the moderation actions
+    power_levels: Option<RoomPowerLevels>,
+    search: String,
+    shown: usize,
+    error: Option<String>,
+    // Dropping the list stops loading members and listening to presence
+    _updates: task::Handle,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    MembersLoaded(Result<Vec<Member>, String>),
+    PresenceChanged(OwnedUserId, PresenceState),
+    PowerLevels(Option<RoomPowerLevels>),
+    AvatarLoaded(OwnedMxcUri, Result<image::Handle, String>),
+    SearchInput(String),
+    ShowMore,
+    Moderate(OwnedUserId, String, moderation::Kind),
+    Verify(OwnedUserId, String),
+}
+
+impl MemberList {
+    pub fn new(room: Room, media: MediaCache) -> (Self, Task<Message>) {
+        let members =
+            Task::run(load_members(room.clone()), Message::MembersLoaded);
+        let presence = Task::run(
+            presence_updates(room.client()),
+            |(user_id, presence)| Message::PresenceChanged(user_id, presence),
+        );
+        let power_levels =
+            Task::perform(power_levels(room.clone()), Message::PowerLevels);
+        let (updates, handle) =
+            Task::batch([members, presence, power_levels]).abortable();
+
+        (
+            Self {
+                room,
+                media,
+                members: None,
+                avatars: HashMap::new(),
+                power_levels: None,
+                search: String::new(),
+                shown: PAGE_SIZE,
+                error: None,
+                _updates: handle.abort_on_drop(),
+            },
+            updates,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::MembersLoaded(result) => match result {
+                Ok(members) => {
+                    self.members = Some(members);
+                    self.error = None;
+                    return self.load_avatars();
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::PresenceChanged(user_id, presence) => {
+                if let Some(member) = self
+                    .members
+                    .iter_mut()
+                    .flatten()
+                    .find(|member| member.user_id == user_id)
+                {
+                    member.presence = Some(presence);
+                }
+            }
+            Message::PowerLevels(power_levels) => {
+                self.power_levels = power_levels;
+            }
+            Message::AvatarLoaded(uri, result) => {
+                self.avatars.insert(uri, result.ok());
+            }
+            Message::SearchInput(string) => {
+                self.search = string;
+                self.shown = PAGE_SIZE;
+                return self.load_avatars();
+            }
+            Message::ShowMore => {
+                self.shown += PAGE_SIZE;
+                return self.load_avatars();
+            }
+            Message::Moderate(user_id, name, kind) => {
+                return Action::Moderate(user_id, name, kind);
+            }
+            Message::Verify(user_id, name) => {
+                return Action::Verify(user_id, name);
+            }
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let search = text_input("Search members", &self.search)
+            .on_input(Message::SearchInput)
+            .size(FONT_SIZE);
+
+        let Some(members) = &self.members else {
+            let status: Element<Message> = match &self.error {
+                Some(error) => text(error).size(FONT_SIZE).into(),
+                None => center_x(
+                    Spinner::new()
+                        .size(20.0)
+                        .bar_height(2.0)
+                        .cycle_duration(Duration::from_secs_f32(1.0)),
+                )
+                .into(),
+            };
+            return column![search, status].spacing(10).padding(10).into();
+        };
+
+        let visible = self.visible_members(members);
+        let total = visible.len();
+
+        let mut list = Column::new().spacing(4);
+        let mut group = None;
+        for member in visible.into_iter().take(self.shown) {
+            if group != Some(member.group) {
+                group = Some(member.group);
+                let count = members
+                    .iter()
+                    .filter(|other| other.group == member.group)
+                    .count();
+                list = list.push(
+                    text(format!("{} — {count}", group_name(member.group)))
+                        .size(FONT_SIZE - 2),
+                );
+            }
+            list = list.push(self.view_member(member));
+        }
+        if total > self.shown {
+            list = list.push(
+                button(text("Show more").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press(Message::ShowMore),
+            );
+        } else if total == 0 {
+            list = list.push(text("No members found").size(FONT_SIZE));
+        }
+        if let Some(error) = &self.error {
+            list = list.push(text(error).size(FONT_SIZE));
+        }
+
+        column![search, scrollable(list).height(Length::Fill)]
+            .spacing(10)
+            .padding(10)
+            .into()
+    }
+
+    fn view_member<'a>(&'a self, member: &'a Member) -> Element<'a, Message> {
+        let picture = match member
+            .avatar_url
+            .as_ref()
+            .and_then(|uri| self.avatars.get(uri))
+        {
+            Some(Some(handle)) => avatar::picture(handle, AVATAR_SIZE),
+            _ => {
+                avatar::view(member.user_id.as_str(), &member.name, AVATAR_SIZE)
+            }
+        };
+        let picture: Element<Message> = match &member.presence {
+            Some(presence) => {
+                stack![picture, bottom_right(presence_dot(presence.clone()))]
+                    .width(AVATAR_SIZE)
+                    .height(AVATAR_SIZE)
+                    .into()
+            }
+            None => picture,
+        };
+
+        let mut details = column![text(&member.name).size(FONT_SIZE)];
+        if member.name != member.user_id.as_str() {
+            details =
+                details.push(text(member.user_id.as_str()).size(FONT_SIZE - 3));
+        }
+
+        let power_level = match member.power_level {
+            UserPowerLevel::Infinite => Some(String::from("Creator")),
+            UserPowerLevel::Int(level) if i64::from(level) != 0 => {
+                Some(level.to_string())
+            }
+            _ => None,
+        };
+
+        let mut line = row![picture, details.width(Length::Fill)]
+            .spacing(8)
+            .align_y(Alignment::Center);
+        if let Some(power_level) = power_level {
+            line = line.push(text(power_level).size(FONT_SIZE - 2));
+        }
+
+        // Verification and moderation actions show on hover, for everyone but
+        // the user
+        if member.user_id == self.room.own_user_id() {
+            return line.into();
+        }
+        let kinds: &[moderation::Kind] = if member.group == Group::Banned {
+            &[moderation::Kind::Unban]
+        } else {
+            &[
+                moderation::Kind::Kick,
+                moderation::Kind::Ban,
+                moderation::Kind::PowerLevel,
+            ]
+        };
+        let kinds = kinds.iter().filter(|kind| {
+            self.power_levels.as_ref().is_some_and(|power_levels| {
+                kind.is_allowed(
+                    power_levels,
+                    self.room.own_user_id(),
+                    &member.user_id,
+                )
+            })
+        });
+        let mut actions = row![].spacing(2);
+        if !matches!(member.group, Group::Invited | Group::Banned) {
+            actions = actions.push(
+                button(text("Verify").size(FONT_SIZE - 3))
+                    .padding([2, 4])
+                    .style(button::secondary)
+                    .on_press(Message::Verify(
+                        member.user_id.clone(),
+                        member.name.clone(),
+                    )),
+            );
+        }
+        for &kind in kinds {
+            actions = actions.push(
+                button(text(kind.label()).size(FONT_SIZE - 3))
+                    .padding([2, 4])
+                    .style(button::secondary)
+                    .on_press(Message::Moderate(
+                        member.user_id.clone(),
+                        member.name.clone(),
+                        kind,
+                    )),
+            );
+        }
+        hover(line, right(actions))
+    }
+
+    /// Members matching the search, in the order they are shown.
+    fn visible_members<'a>(&self, members: &'a [Member]) -> Vec<&'a Member> {
+        let query = self.search.trim().to_lowercase();
+        members
+            .iter()
+            .filter(|member| {
+                query.is_empty()
+                    || member.name.to_lowercase().contains(&query)
+                    || member.user_id.as_str().to_lowercase().contains(&query)
+            })
+            .collect()
+    }
+
+    /// Fetches the avatars of the members currently shown. Avatars further
+    /// down are loaded once "Show more" is pressed.
+    fn load_avatars(&mut self) -> Action {
+        let Some(members) = &self.members else {
+            return Action::None;
+        };
+
+        let mut tasks = Vec::new();
+        for member in self.visible_members(members).into_iter().take(self.shown)
+        {
+            let Some(uri) = &member.avatar_url else {
+                continue;
+            };
+            if self.avatars.contains_key(uri) {
+                continue;
+            }
+            self.avatars.insert(uri.clone(), None);
+            let uri = uri.clone();
+            tasks.push(Task::perform(
+                self.media.clone().avatar(self.room.client(), uri.clone()),
+                move |result| Message::AvatarLoaded(uri.clone(), result),
+            ));
+        }
+
+        Action::Task(Task::batch(tasks))
+    }
+}
+
+fn group_name(group: Group) -> &'static str {
+    match group {
+        Group::Admins => "Admins",
+        Group::Moderators => "Moderators",
+        Group::Members => "Members",
+        Group::Invited => "Invited",
+        Group::Banned => "Banned",
+    }
+}
+
+fn presence_dot<'a, Message: 'a>(
+    presence: PresenceState,
+) -> Element<'a, Message> {
+    container("")
+        .width(PRESENCE_DOT_SIZE)
+        .height(PRESENCE_DOT_SIZE)
+        .style(move |theme: &Theme| {
+            let palette = theme.extended_palette();
+            let color = match presence {
+                PresenceState::Online => palette.success.base.color,
+                PresenceState::Unavailable => palette.warning.base.color,
+                _ => Color::from_rgb(0.5, 0.5, 0.5),
+            };
+            container::Style {
+                background: Some(Background::Color(color)),
+                border: Border {
+                    radius: (PRESENCE_DOT_SIZE / 2.0).into(),
+                    width: 1.5,
+                    color: palette.background.base.color,
+                },
+                ..container::Style::default()
+            }
+        })
+        .into()
+}
+
+/// The members we already know about, then the complete list once it has
+/// been fetched. Large rooms only send the members that appear in the
+/// timeline up front.
+fn load_members(room: Room) -> impl Stream<Item = Result<Vec<Member>, String>> {
+    let known = stream::once({
+        let room = room.clone();
+        async move {
+            let members = room
+                .members_no_sync(memberships())
+                .await
+                .map_err(|error| error.to_string())?;
+            Ok(to_members(&room, members).await)
+        }
+    });
+    let all = stream::once(async move {
+        let members = room
+            .members(memberships())
+            .await
+            .map_err(|error| error.to_string())?;
+        Ok(to_members(&room, members).await)
+    });
+
+    known.chain(all)
+}
+
+fn memberships() -> RoomMemberships {
+    RoomMemberships::JOIN | RoomMemberships::INVITE | RoomMemberships::BAN
+}
+
+async fn power_levels(room: Room) -> Option<RoomPowerLevels> {
+    room.power_levels().await.ok()
+}
+
+/// Sorts members by group then name, with the last presence seen for them.
+async fn to_members(room: &Room, members: Vec<RoomMember>) -> Vec<Member> {
+    let user_ids: Vec<OwnedUserId> = members
+        .iter()
+        .map(|member| member.user_id().to_owned())
+        .collect();
+    let presence: HashMap<OwnedUserId, PresenceState> = room
+        .client()
+        .state_store()
+        .get_presence_events(&user_ids)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|raw| raw.deserialize().ok())
+        .map(|event| (event.sender, event.content.presence))
+        .collect();
+
+    let mut members: Vec<Member> = members
+        .into_iter()
+        .map(|member| {
+            let group = match member.membership() {
+                MembershipState::Invite => Group::Invited,
+                MembershipState::Ban => Group::Banned,
+                _ => match member.suggested_role_for_power_level() {
+                    RoomMemberRole::Creator | RoomMemberRole::Administrator => {
+                        Group::Admins
+                    }
+                    RoomMemberRole::Moderator => Group::Moderators,
+                    RoomMemberRole::User => Group::Members,
+                },
+            };
+            Member {
+                name: member.name().to_string(),
+                avatar_url: member.avatar_url().map(ToOwned::to_owned),
+                power_level: member.power_level(),
+                presence: presence.get(member.user_id()).cloned(),
+                user_id: member.user_id().to_owned(),
+                group,
+            }
+        })
+        .collect();
+
+    members.sort_by_cached_key(|member| {
+        (member.group, member.name.to_lowercase())
+    });
+    members
+}
+
+/// Presence changes of any user, as they arrive in syncs.
+fn presence_updates(
+    client: Client,
+) -> impl Stream<Item = (OwnedUserId, PresenceState)> {
+    iced::stream::channel(16, async move |output| {
+        let handle = client.add_event_handler(move |event: PresenceEvent| {
+            let mut output = output.clone();
+            async move {
+                let _ =
+                    output.send((event.sender, event.content.presence)).await;
+            }
+        });
+        let _guard = client.event_handler_drop_guard(handle);
+
+        // Listen until the list is closed
+        future::pending::<()>().await;
+    })
+}
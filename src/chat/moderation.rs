@@ -0,0 +1,368 @@
+// Confirmation dialog for kicking, banning and unbanning users and changing
+// their power level
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::widget::button;
+use iced::widget::column;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Room;
+use matrix_sdk::ruma::Int;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::events::StateEventType;
+use matrix_sdk::ruma::events::room::power_levels::PowerLevelAction;
+use matrix_sdk::ruma::events::room::power_levels::PowerLevelUserAction;
+use matrix_sdk::ruma::events::room::power_levels::RoomPowerLevels;
+use matrix_sdk::ruma::events::room::power_levels::UserPowerLevel;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 400.0;
+// Levels offered as shortcuts when changing a power level
+const PRESETS: [(&str, i64); 3] =
+    [("User", 0), ("Moderator", 50), ("Admin", 100)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Kick,
+    Ban,
+    Unban,
+    PowerLevel,
+}
+
+impl Kind {
+    /// Short name for buttons that open the dialog.
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::PowerLevel => "Power level",
+            kind => kind.verb(),
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            Kind::Kick => "Kick",
+            Kind::Ban => "Ban",
+            Kind::Unban => "Unban",
+            Kind::PowerLevel => "Change power level of",
+        }
+    }
+
+    fn needs_reason(self) -> bool {
+        self != Kind::PowerLevel
+    }
+
+    /// Whether `user_id` may do this to `target` under `power_levels`.
+    pub fn is_allowed(
+        self,
+        power_levels: &RoomPowerLevels,
+        user_id: &UserId,
+        target: &UserId,
+    ) -> bool {
+        let action = match self {
+            Kind::Kick => PowerLevelUserAction::Kick,
+            Kind::Ban => PowerLevelUserAction::Ban,
+            Kind::Unban => PowerLevelUserAction::Unban,
+            Kind::PowerLevel => PowerLevelUserAction::ChangePowerLevel,
+        };
+        power_levels.user_can_do_to_user(user_id, target, action)
+    }
+}
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Done,
+    Close,
+}
+
+pub struct Moderation {
+    room: Room,
+    user_id: OwnedUserId,
+    name: String,
+    kind: Kind,
+    power_levels: Option<RoomPowerLevels>,
+    reason: String,
+    level: String,
+    running: bool,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    PowerLevelsLoaded(Result<RoomPowerLevels, String>),
+    ReasonInput(String),
+    LevelInput(String),
+    Confirm,
+    Finished(Result<(), String>),
+    Close,
+}
+
+impl Moderation {
+    pub fn new(
+        room: Room,
+        user_id: OwnedUserId,
+        name: String,
+        kind: Kind,
+    ) -> (Self, Task<Message>) {
+        let task = Task::perform(
+            {
+                let room = room.clone();
+                async move {
+                    room.power_levels().await.map_err(|error| error.to_string())
+                }
+            },
+            Message::PowerLevelsLoaded,
+        );
+
+        (
+            Self {
+                room,
+                user_id,
+                name,
+                kind,
+                power_levels: None,
+                reason: String::new(),
+                level: String::new(),
+                running: false,
+                error: None,
+            },
+            task,
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::PowerLevelsLoaded(result) => match result {
+                Ok(power_levels) => {
+                    if let UserPowerLevel::Int(level) =
+                        power_levels.for_user(&self.user_id)
+                    {
+                        self.level = level.to_string();
+                    }
+                    self.power_levels = Some(power_levels);
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::ReasonInput(reason) => self.reason = reason,
+            Message::LevelInput(level) => self.level = level,
+            Message::Confirm => {
+                let level = if self.kind == Kind::PowerLevel {
+                    match self.new_level() {
+                        Ok(level) => Some(level),
+                        Err(error) => {
+                            self.error = Some(error);
+                            return Action::None;
+                        }
+                    }
+                } else {
+                    None
+                };
+                let reason = Some(self.reason.trim().to_string())
+                    .filter(|reason| !reason.is_empty());
+
+                self.running = true;
+                self.error = None;
+                return Action::Task(Task::perform(
+                    moderate(
+                        self.room.clone(),
+                        self.user_id.clone(),
+                        self.kind,
+                        reason,
+                        level,
+                    ),
+                    Message::Finished,
+                ));
+            }
+            Message::Finished(result) => {
+                self.running = false;
+                match result {
+                    Ok(()) => return Action::Done,
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut content = column![
+            text(format!("{} {}", self.kind.verb(), self.name)).size(20),
+            text(self.user_id.as_str()).size(FONT_SIZE - 2),
+            rule::horizontal(1),
+        ]
+        .spacing(10)
+        .width(WIDTH);
+
+        let allowed = match &self.power_levels {
+            Some(power_levels) => {
+                content = content
+                    .push(text(self.requirement(power_levels)).size(FONT_SIZE));
+                self.is_allowed(power_levels)
+            }
+            None => {
+                content = content.push(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                );
+                false
+            }
+        };
+
+        if self.kind.needs_reason() {
+            content = content.push(
+                text_input("Reason (optional)", &self.reason)
+                    .on_input(Message::ReasonInput)
+                    .size(FONT_SIZE),
+            );
+        } else {
+            let mut presets = row![
+                text_input("Power level", &self.level)
+                    .on_input(Message::LevelInput)
+                    .size(FONT_SIZE)
+                    .width(100)
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center);
+            for (label, level) in PRESETS {
+                presets = presets.push(
+                    button(text(label).size(FONT_SIZE - 1))
+                        .style(button::secondary)
+                        .on_press(Message::LevelInput(level.to_string())),
+                );
+            }
+            content = content.push(presets);
+        }
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        let mut buttons = row![text("").width(Length::Fill)]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        if self.running {
+            buttons = buttons.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        buttons = buttons
+            .push(
+                button(text("Cancel").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press(Message::Close),
+            )
+            .push(
+                button(
+                    text(match self.kind {
+                        Kind::PowerLevel => "Change",
+                        kind => kind.verb(),
+                    })
+                    .size(FONT_SIZE),
+                )
+                .style(match self.kind {
+                    Kind::Kick | Kind::Ban => button::danger,
+                    Kind::Unban | Kind::PowerLevel => button::primary,
+                })
+                .on_press_maybe(
+                    (allowed && !self.running).then_some(Message::Confirm),
+                ),
+            );
+
+        content.push(buttons).into()
+    }
+
+    /// What power level the action needs and how it compares to the user's.
+    fn requirement(&self, power_levels: &RoomPowerLevels) -> String {
+        let required = match self.kind {
+            Kind::Kick => power_levels.for_action(PowerLevelAction::Kick),
+            Kind::Ban => power_levels.for_action(PowerLevelAction::Ban),
+            Kind::Unban => power_levels.for_action(PowerLevelAction::Unban),
+            Kind::PowerLevel => {
+                power_levels.for_state(StateEventType::RoomPowerLevels)
+            }
+        };
+        let own = describe(power_levels.for_user(self.room.own_user_id()));
+        let target = describe(power_levels.for_user(&self.user_id));
+
+        let mut requirement = format!(
+            "Requires power level {required}. Yours is {own}, {}'s is \
+             {target}.",
+            self.name
+        );
+        if !self.is_allowed(power_levels) {
+            requirement.push_str(match self.kind {
+                Kind::PowerLevel => {
+                    " You can only change the power level of users below you."
+                }
+                _ => " You can only do this to users below you.",
+            });
+        }
+        requirement
+    }
+
+    fn is_allowed(&self, power_levels: &RoomPowerLevels) -> bool {
+        self.kind.is_allowed(
+            power_levels,
+            self.room.own_user_id(),
+            &self.user_id,
+        )
+    }
+
+    /// The level typed in, which can't be above the user's own.
+    fn new_level(&self) -> Result<Int, String> {
+        let level = self
+            .level
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .and_then(Int::new)
+            .ok_or_else(|| format!("{} is not a power level", self.level))?;
+
+        if let Some(power_levels) = &self.power_levels
+            && let UserPowerLevel::Int(own) =
+                power_levels.for_user(self.room.own_user_id())
+            && level > own
+        {
+            return Err(String::from(
+                "You can't give a higher power level than your own",
+            ));
+        }
+        Ok(level)
+    }
+}
+
+fn describe(level: UserPowerLevel) -> String {
+    match level {
+        UserPowerLevel::Int(level) => level.to_string(),
+        _ => String::from("creator"),
+    }
+}
+
+async fn moderate(
+    room: Room,
+    user_id: OwnedUserId,
+    kind: Kind,
+    reason: Option<String>,
+    level: Option<Int>,
+) -> Result<(), String> {
+    let reason = reason.as_deref();
+    match (kind, level) {
+        (Kind::Kick, _) => room.kick_user(&user_id, reason).await,
+        (Kind::Ban, _) => room.ban_user(&user_id, reason).await,
+        (Kind::Unban, _) => room.unban_user(&user_id, reason).await,
+        (Kind::PowerLevel, Some(level)) => room
+            .update_power_levels(vec![(&user_id, level)])
+            .await
+            .map(|_response| ()),
+        (Kind::PowerLevel, None) => Ok(()),
+    }
+    .map_err(|error| error.to_string())
+}
@@ -0,0 +1,299 @@
+// Dialog for starting a direct message with someone from the user directory
+use crate::chat::avatar;
+use crate::chat::media::MediaCache;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::image;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::ruma::OwnedMxcUri;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::sleep::sleep;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 420.0;
+const LIST_HEIGHT: f32 = 320.0;
+const AVATAR_SIZE: f32 = 32.0;
+const MAX_RESULTS: u64 = 20;
+// Waits for typing to pause before searching
+const SEARCH_DELAY: Duration = Duration::from_millis(300);
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Opened(Room),
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    user_id: OwnedUserId,
+    name: Option<String>,
+    avatar_url: Option<OwnedMxcUri>,
+}
+
+pub struct NewDm {
+    client: Client,
+    media: MediaCache,
+    search: String,
+    results: Vec<User>,
+    // None while loading or when the avatar couldn't be loaded
+    avatars: HashMap<OwnedMxcUri, Option<image::Handle>>,
+    // Dropping the handle cancels an outdated search
+    searching: Option<task::Handle>,
+    // The user a DM is being created with
+    starting: Option<OwnedUserId>,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    SearchInput(String),
+    Searched(Result<Vec<User>, String>),
+    AvatarLoaded(OwnedMxcUri, Result<image::Handle, String>),
+    Start(OwnedUserId),
+    Started(Result<Room, String>),
+    Close,
+}
+
+impl NewDm {
+    pub fn new(client: Client, media: MediaCache) -> Self {
+        Self {
+            client,
+            media,
+            search: String::new(),
+            results: Vec::new(),
+            avatars: HashMap::new(),
+            searching: None,
+            starting: None,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::SearchInput(search) => {
+                self.search = search;
+                self.error = None;
+                if self.search.trim().is_empty() {
+                    self.searching = None;
+                    self.results.clear();
+                    return Action::None;
+                }
+                return Action::Task(self.start_search());
+            }
+            Message::Searched(result) => {
+                self.searching = None;
+                match result {
+                    Ok(results) => {
+                        self.results = results;
+                        return self.load_avatars();
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::AvatarLoaded(uri, result) => {
+                self.avatars.insert(uri, result.ok());
+            }
+            Message::Start(user_id) => {
+                if self.starting.is_some() {
+                    return Action::None;
+                }
+                // Reuse the DM listed in `m.direct` if there already is one
+                if let Some(room) = self.client.get_dm_room(&user_id) {
+                    return Action::Opened(room);
+                }
+
+                self.starting = Some(user_id.clone());
+                self.error = None;
+                let client = self.client.clone();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .create_dm(&user_id)
+                            .await
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::Started,
+                ));
+            }
+            Message::Started(result) => {
+                self.starting = None;
+                match result {
+                    Ok(room) => return Action::Opened(room),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut list = Column::new().spacing(6);
+        for user in &self.results {
+            list = list.push(self.view_user(user));
+        }
+        if self.searching.is_some() {
+            list = list.push(center_x(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            ));
+        } else if self.results.is_empty() && !self.search.trim().is_empty() {
+            list = list.push(text("No users found").size(FONT_SIZE));
+        }
+        if let Some(error) = &self.error {
+            list = list.push(text(error).size(FONT_SIZE));
+        }
+
+        column![
+            row![
+                text("New direct message").size(20).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE)).on_press(Message::Close)
+            ]
+            .align_y(Alignment::Center),
+            rule::horizontal(1),
+            text_input("Search by name or user ID", &self.search)
+                .on_input(Message::SearchInput)
+                .size(FONT_SIZE),
+            scrollable(list).height(LIST_HEIGHT)
+        ]
+        .spacing(10)
+        .width(WIDTH)
+        .into()
+    }
+
+    fn view_user<'a>(&'a self, user: &'a User) -> Element<'a, Message> {
+        let name = user.name.as_deref().unwrap_or(user.user_id.as_str());
+        let picture = match user
+            .avatar_url
+            .as_ref()
+            .and_then(|uri| self.avatars.get(uri))
+        {
+            Some(Some(handle)) => avatar::picture(handle, AVATAR_SIZE),
+            _ => avatar::view(user.user_id.as_str(), name, AVATAR_SIZE),
+        };
+
+        let mut details = column![text(name).size(FONT_SIZE)];
+        if user.name.is_some() {
+            details =
+                details.push(text(user.user_id.as_str()).size(FONT_SIZE - 3));
+        }
+
+        let has_dm = self.client.get_dm_room(&user.user_id).is_some();
+        let is_starting = self.starting.as_ref() == Some(&user.user_id);
+        let mut line = row![picture, details.width(Length::Fill)]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        if is_starting {
+            line = line.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        line.push(
+            button(
+                text(if has_dm { "Open" } else { "Message" }).size(FONT_SIZE),
+            )
+            .style(if has_dm {
+                button::secondary
+            } else {
+                button::primary
+            })
+            .on_press_maybe(
+                self.starting
+                    .is_none()
+                    .then(|| Message::Start(user.user_id.clone())),
+            ),
+        )
+        .into()
+    }
+
+    fn start_search(&mut self) -> Task<Message> {
+        let client = self.client.clone();
+        let search = self.search.trim().to_string();
+        let (task, handle) = Task::perform(
+            async move {
+                sleep(SEARCH_DELAY).await;
+                search_users(client, search).await
+            },
+            Message::Searched,
+        )
+        .abortable();
+        self.searching = Some(handle.abort_on_drop());
+        task
+    }
+
+    fn load_avatars(&mut self) -> Action {
+        let mut tasks = Vec::new();
+        for uri in self
+            .results
+            .iter()
+            .filter_map(|user| user.avatar_url.clone())
+        {
+            if self.avatars.contains_key(&uri) {
+                continue;
+            }
+            self.avatars.insert(uri.clone(), None);
+            tasks.push(Task::perform(
+                self.media.clone().avatar(self.client.clone(), uri.clone()),
+                move |result| Message::AvatarLoaded(uri.clone(), result),
+            ));
+        }
+        Action::Task(Task::batch(tasks))
+    }
+}
+
+/// Users matching `search` in the directory. A full user ID is offered even
+/// when the directory doesn't list it, since servers only return users that
+/// share a room with us or are in public rooms.
+async fn search_users(
+    client: Client,
+    search: String,
+) -> Result<Vec<User>, String> {
+    let response = client
+        .search_users(&search, MAX_RESULTS)
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let mut users: Vec<User> = response
+        .results
+        .into_iter()
+        .filter(|user| Some(&*user.user_id) != client.user_id())
+        .map(|user| User {
+            user_id: user.user_id,
+            name: user.display_name,
+            avatar_url: user.avatar_url,
+        })
+        .collect();
+
+    if let Ok(user_id) = UserId::parse(&search)
+        && !users.iter().any(|user| user.user_id == user_id)
+    {
+        users.insert(
+            0,
+            User {
+                user_id,
+                name: None,
+                avatar_url: None,
+            },
+        );
+    }
+
+    Ok(users)
+}
@@ -0,0 +1,362 @@
+// Browses the public rooms published by a homeserver, and joins rooms by
+// address
+use crate::chat::avatar;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::RoomState;
+use matrix_sdk::ruma::MatrixToUri;
+use matrix_sdk::ruma::MatrixUri;
+use matrix_sdk::ruma::OwnedRoomOrAliasId;
+use matrix_sdk::ruma::OwnedServerName;
+use matrix_sdk::ruma::RoomId;
+use matrix_sdk::ruma::RoomOrAliasId;
+use matrix_sdk::ruma::ServerName;
+use matrix_sdk::ruma::UInt;
+use matrix_sdk::ruma::api::client::directory::get_public_rooms_filtered::v3::Request;
+use matrix_sdk::ruma::directory::Filter;
+use matrix_sdk::ruma::directory::PublicRoomsChunk;
+use matrix_sdk::ruma::matrix_uri::MatrixId;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 520.0;
+const LIST_HEIGHT: f32 = 380.0;
+const AVATAR_SIZE: f32 = 32.0;
+const PAGE_SIZE: u32 = 30;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Joined(Room),
+    Close,
+}
+
+pub struct RoomDirectory {
+    client: Client,
+    // Empty for the user's own homeserver
+    server: String,
+    search: String,
+    rooms: Vec<PublicRoomsChunk>,
+    // Token for the next page, None once every room has been listed
+    next_batch: Option<String>,
+    // Dropping the handle discards results of an outdated search
+    loading: Option<task::Handle>,
+    error: Option<String>,
+    address: String,
+    joining: bool,
+    join_error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    ServerInput(String),
+    SearchInput(String),
+    Search,
+    LoadMore,
+    Loaded(Result<(Vec<PublicRoomsChunk>, Option<String>), String>),
+    Join(OwnedRoomOrAliasId, Vec<OwnedServerName>),
+    AddressInput(String),
+    JoinAddress,
+    Joined(Result<Room, String>),
+    Close,
+}
+
+impl RoomDirectory {
+    pub fn new(client: Client) -> (Self, Task<Message>) {
+        let mut directory = Self {
+            client,
+            server: String::new(),
+            search: String::new(),
+            rooms: Vec::new(),
+            next_batch: None,
+            loading: None,
+            error: None,
+            address: String::new(),
+            joining: false,
+            join_error: None,
+        };
+        let task = directory.load(None);
+        (directory, task)
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::ServerInput(server) => self.server = server,
+            Message::SearchInput(search) => self.search = search,
+            Message::Search => {
+                self.rooms.clear();
+                self.next_batch = None;
+                return Action::Task(self.load(None));
+            }
+            Message::LoadMore => {
+                let since = self.next_batch.clone();
+                return Action::Task(self.load(since));
+            }
+            Message::Loaded(result) => {
+                self.loading = None;
+                match result {
+                    Ok((rooms, next_batch)) => {
+                        self.rooms.extend(rooms);
+                        self.next_batch = next_batch;
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Join(room, via) => return self.join(room, via),
+            Message::AddressInput(address) => {
+                self.address = address;
+                self.join_error = None;
+            }
+            Message::JoinAddress => match parse_address(&self.address) {
+                Ok((room, via)) => return self.join(room, via),
+                Err(error) => self.join_error = Some(error),
+            },
+            Message::Joined(result) => {
+                self.joining = false;
+                match result {
+                    Ok(room) => return Action::Joined(room),
+                    Err(error) => self.join_error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let join = row![
+            text_input("#room:example.org or a matrix.to link", &self.address)
+                .on_input(Message::AddressInput)
+                .on_submit(Message::JoinAddress)
+                .size(FONT_SIZE),
+            button(text("Join").size(FONT_SIZE)).on_press_maybe(
+                (!self.joining && !self.address.trim().is_empty())
+                    .then_some(Message::JoinAddress)
+            )
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+
+        let search = row![
+            text_input("Homeserver", &self.server)
+                .on_input(Message::ServerInput)
+                .on_submit(Message::Search)
+                .size(FONT_SIZE)
+                .width(150),
+            text_input("Search rooms", &self.search)
+                .on_input(Message::SearchInput)
+                .on_submit(Message::Search)
+                .size(FONT_SIZE),
+            button(text("Search").size(FONT_SIZE))
+                .style(button::secondary)
+                .on_press(Message::Search)
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+
+        let mut list = Column::new().spacing(8);
+        for room in &self.rooms {
+            list = list.push(self.view_room(room));
+        }
+        if self.loading.is_some() {
+            list = list.push(center_x(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            ));
+        } else if self.next_batch.is_some() {
+            list = list.push(center_x(
+                button(text("Load more").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press(Message::LoadMore),
+            ));
+        } else if self.rooms.is_empty() && self.error.is_none() {
+            list = list.push(text("No rooms found").size(FONT_SIZE));
+        }
+        if let Some(error) = &self.error {
+            list = list.push(text(error).size(FONT_SIZE));
+        }
+
+        let mut content = column![
+            row![
+                text("Explore rooms").size(20).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE)).on_press(Message::Close)
+            ]
+            .align_y(Alignment::Center),
+            rule::horizontal(1),
+            join,
+        ]
+        .spacing(10)
+        .width(WIDTH);
+        if self.joining {
+            content = content.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        if let Some(error) = &self.join_error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        content
+            .push(rule::horizontal(1))
+            .push(search)
+            .push(scrollable(list).height(LIST_HEIGHT))
+            .into()
+    }
+
+    fn view_room<'a>(
+        &self,
+        room: &'a PublicRoomsChunk,
+    ) -> Element<'a, Message> {
+        let name = room
+            .name
+            .as_deref()
+            .or(room.canonical_alias.as_ref().map(|alias| alias.as_str()))
+            .unwrap_or(room.room_id.as_str());
+
+        let mut details = column![
+            row![
+                text(name).size(FONT_SIZE),
+                text(format!("{} members", room.num_joined_members))
+                    .size(FONT_SIZE - 3)
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center)
+        ]
+        .spacing(2);
+        if let Some(alias) = &room.canonical_alias {
+            details = details.push(text(alias.as_str()).size(FONT_SIZE - 3));
+        }
+        if let Some(topic) = &room.topic {
+            details = details.push(text(topic).size(FONT_SIZE - 2));
+        }
+
+        let is_joined = self
+            .client
+            .get_room(&room.room_id)
+            .is_some_and(|room| room.state() == RoomState::Joined);
+        let via = self.server().into_iter().collect();
+        let action = button(
+            text(if is_joined { "View" } else { "Join" }).size(FONT_SIZE),
+        )
+        .style(if is_joined {
+            button::secondary
+        } else {
+            button::primary
+        })
+        .on_press_maybe(
+            (!self.joining)
+                .then(|| Message::Join(room.room_id.clone().into(), via)),
+        );
+
+        row![
+            avatar::view(room.room_id.as_str(), name, AVATAR_SIZE),
+            details.width(Length::Fill),
+            action
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center)
+        .into()
+    }
+
+    /// The homeserver typed in, if it's a valid server name.
+    fn server(&self) -> Option<OwnedServerName> {
+        ServerName::parse(self.server.trim()).ok()
+    }
+
+    fn load(&mut self, since: Option<String>) -> Task<Message> {
+        self.error = None;
+
+        let mut request = Request::new();
+        request.server = self.server();
+        request.limit = Some(UInt::from(PAGE_SIZE));
+        request.since = since;
+        request.filter = Filter::new();
+        request.filter.generic_search_term =
+            Some(self.search.trim().to_string())
+                .filter(|search| !search.is_empty());
+
+        let client = self.client.clone();
+        let (task, handle) = Task::perform(
+            async move {
+                client
+                    .public_rooms_filtered(request)
+                    .await
+                    .map(|response| (response.chunk, response.next_batch))
+                    .map_err(|error| error.to_string())
+            },
+            Message::Loaded,
+        )
+        .abortable();
+        self.loading = Some(handle.abort_on_drop());
+        task
+    }
+
+    fn join(
+        &mut self,
+        room: OwnedRoomOrAliasId,
+        via: Vec<OwnedServerName>,
+    ) -> Action {
+        // Rooms that are already joined are just opened
+        if let Ok(room_id) = <&RoomId>::try_from(&*room)
+            && let Some(room) = self.client.get_room(room_id)
+            && room.state() == RoomState::Joined
+        {
+            return Action::Joined(room);
+        }
+
+        self.joining = true;
+        self.join_error = None;
+        let client = self.client.clone();
+        Action::Task(Task::perform(
+            async move {
+                client
+                    .join_room_by_id_or_alias(&room, &via)
+                    .await
+                    .map_err(|error| error.to_string())
+            },
+            Message::Joined,
+        ))
+    }
+}
+
+/// Reads a room alias, room ID, matrix.to link or `matrix:` URI.
+fn parse_address(
+    address: &str,
+) -> Result<(OwnedRoomOrAliasId, Vec<OwnedServerName>), String> {
+    let address = address.trim();
+
+    let (id, via) = if let Ok(uri) = MatrixToUri::parse(address) {
+        (uri.id().clone(), uri.via().to_vec())
+    } else if let Ok(uri) = MatrixUri::parse(address) {
+        (uri.id().clone(), uri.via().to_vec())
+    } else {
+        return RoomOrAliasId::parse(address)
+            .map(|room| (room, Vec::new()))
+            .map_err(|_error| {
+                String::from("Enter a room address like #room:example.org")
+            });
+    };
+
+    let room = match id {
+        MatrixId::Room(room_id) => room_id.into(),
+        MatrixId::RoomAlias(alias) => alias.into(),
+        MatrixId::Event(room, _event_id) => room,
+        _ => return Err(String::from("That link isn't for a room")),
+    };
+    Ok((room, via))
+}
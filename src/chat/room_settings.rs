@@ -0,0 +1,656 @@
+// Dialog for changing a room's name, topic, avatar, access and aliases
+use crate::chat::avatar;
+use crate::chat::create_room::History;
+use crate::chat::media::MediaCache;
+use crate::chat::room_name;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::checkbox;
+use iced::widget::column;
+use iced::widget::image;
+use iced::widget::pick_list;
+use iced::widget::radio;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Room;
+use matrix_sdk::ruma::OwnedRoomAliasId;
+use matrix_sdk::ruma::RoomAliasId;
+use matrix_sdk::ruma::events::StateEventType;
+use matrix_sdk::ruma::events::room::guest_access::GuestAccess;
+use matrix_sdk::ruma::events::room::guest_access::RoomGuestAccessEventContent;
+use matrix_sdk::ruma::events::room::join_rules::JoinRule;
+use std::fs;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 480.0;
+const HEIGHT: f32 = 560.0;
+const LABEL_WIDTH: f32 = 90.0;
+const AVATAR_SIZE: f32 = 56.0;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Close,
+}
+
+/// The join rules that can be picked. Rooms with other rules, such as
+/// restricted to a space's members, show no selection until one is picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Invite,
+    Knock,
+    Public,
+}
+
+impl Access {
+    fn from_join_rule(join_rule: Option<JoinRule>) -> Option<Self> {
+        match join_rule? {
+            JoinRule::Invite => Some(Access::Invite),
+            JoinRule::Knock => Some(Access::Knock),
+            JoinRule::Public => Some(Access::Public),
+            _ => None,
+        }
+    }
+
+    fn join_rule(self) -> JoinRule {
+        match self {
+            Access::Invite => JoinRule::Invite,
+            Access::Knock => JoinRule::Knock,
+            Access::Public => JoinRule::Public,
+        }
+    }
+}
+
+/// Everything saved with the Save button.
+#[derive(Debug, Clone, PartialEq)]
+struct Settings {
+    name: String,
+    topic: String,
+    access: Option<Access>,
+    guest_access: bool,
+    history: History,
+    encrypted: bool,
+    canonical_alias: Option<OwnedRoomAliasId>,
+    alt_aliases: Vec<OwnedRoomAliasId>,
+}
+
+impl Settings {
+    fn of(room: &Room) -> Self {
+        Self {
+            name: room.name().unwrap_or_default(),
+            topic: room.topic().unwrap_or_default(),
+            access: Access::from_join_rule(room.join_rule()),
+            guest_access: room.guest_access() == GuestAccess::CanJoin,
+            history: History::from_visibility(
+                &room.history_visibility_or_default(),
+            ),
+            encrypted: room.encryption_state().is_encrypted(),
+            canonical_alias: room.canonical_alias(),
+            alt_aliases: room.alt_aliases(),
+        }
+    }
+
+    fn aliases(&self) -> impl Iterator<Item = &OwnedRoomAliasId> {
+        self.canonical_alias.iter().chain(&self.alt_aliases)
+    }
+}
+
+/// Which settings the user's power level lets them change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Permissions {
+    name: bool,
+    topic: bool,
+    avatar: bool,
+    join_rules: bool,
+    guest_access: bool,
+    history: bool,
+    encryption: bool,
+    aliases: bool,
+}
+
+pub struct RoomSettings {
+    room: Room,
+    saved: Settings,
+    edited: Settings,
+    // Everything is read only until the power levels are loaded
+    permissions: Permissions,
+    avatar: Option<image::Handle>,
+    new_alias: String,
+    saving: bool,
+    changing_avatar: bool,
+    status: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    PermissionsLoaded(Result<Permissions, String>),
+    AvatarLoaded(Result<image::Handle, String>),
+    NameInput(String),
+    TopicInput(String),
+    AccessSelected(Access),
+    ToggleGuestAccess(bool),
+    HistorySelected(History),
+    EnableEncryption(bool),
+    NewAliasInput(String),
+    AddAlias,
+    RemoveAlias(OwnedRoomAliasId),
+    MakeMainAlias(OwnedRoomAliasId),
+    ChangeAvatar,
+    RemoveAvatar,
+    AvatarChanged(Result<bool, String>),
+    Save,
+    Saved(Result<(), String>),
+    Close,
+}
+
+impl RoomSettings {
+    pub fn new(room: Room, media: MediaCache) -> (Self, Task<Message>) {
+        let settings = Settings::of(&room);
+        let mut tasks = vec![Task::perform(
+            permissions(room.clone()),
+            Message::PermissionsLoaded,
+        )];
+        if let Some(uri) = room.avatar_url() {
+            tasks.push(Task::perform(
+                media.clone().avatar(room.client(), uri),
+                Message::AvatarLoaded,
+            ));
+        }
+
+        (
+            Self {
+                room,
+                saved: settings.clone(),
+                edited: settings,
+                permissions: Permissions::default(),
+                avatar: None,
+                new_alias: String::new(),
+                saving: false,
+                changing_avatar: false,
+                status: None,
+            },
+            Task::batch(tasks),
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::PermissionsLoaded(result) => match result {
+                Ok(permissions) => self.permissions = permissions,
+                Err(error) => self.status = Some(error),
+            },
+            Message::AvatarLoaded(result) => self.avatar = result.ok(),
+            Message::NameInput(name) => self.edited.name = name,
+            Message::TopicInput(topic) => self.edited.topic = topic,
+            Message::AccessSelected(access) => {
+                self.edited.access = Some(access);
+            }
+            Message::ToggleGuestAccess(guest_access) => {
+                self.edited.guest_access = guest_access;
+            }
+            Message::HistorySelected(history) => self.edited.history = history,
+            Message::EnableEncryption(encrypted) => {
+                // Encryption can't be turned off again
+                self.edited.encrypted = encrypted || self.saved.encrypted;
+            }
+            Message::NewAliasInput(alias) => self.new_alias = alias,
+            Message::AddAlias => match self.parse_alias() {
+                Ok(alias) => {
+                    if !self.edited.aliases().any(|a| *a == alias) {
+                        if self.edited.canonical_alias.is_none() {
+                            self.edited.canonical_alias = Some(alias);
+                        } else {
+                            self.edited.alt_aliases.push(alias);
+                        }
+                    }
+                    self.new_alias.clear();
+                    self.status = None;
+                }
+                Err(error) => self.status = Some(error),
+            },
+            Message::RemoveAlias(alias) => {
+                self.edited.alt_aliases.retain(|a| *a != alias);
+                if self.edited.canonical_alias.as_ref() == Some(&alias) {
+                    // The next alias becomes the main one
+                    self.edited.canonical_alias =
+                        (!self.edited.alt_aliases.is_empty())
+                            .then(|| self.edited.alt_aliases.remove(0));
+                }
+            }
+            Message::MakeMainAlias(alias) => {
+                self.edited.alt_aliases.retain(|a| *a != alias);
+                if let Some(main) = self.edited.canonical_alias.replace(alias) {
+                    self.edited.alt_aliases.insert(0, main);
+                }
+            }
+            Message::ChangeAvatar => {
+                self.changing_avatar = true;
+                self.status = None;
+                return Action::Task(Task::perform(
+                    upload_avatar(self.room.clone()),
+                    Message::AvatarChanged,
+                ));
+            }
+            Message::RemoveAvatar => {
+                self.changing_avatar = true;
+                self.status = None;
+                let room = self.room.clone();
+                return Action::Task(Task::perform(
+                    async move {
+                        room.remove_avatar()
+                            .await
+                            .map(|_response| true)
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::AvatarChanged,
+                ));
+            }
+            Message::AvatarChanged(result) => {
+                self.changing_avatar = false;
+                match result {
+                    // The new avatar shows up once the state event syncs
+                    Ok(true) => {
+                        self.avatar = None;
+                        self.status = Some(String::from("Avatar updated"));
+                    }
+                    Ok(false) => (),
+                    Err(error) => self.status = Some(error),
+                }
+            }
+            Message::Save => {
+                self.saving = true;
+                self.status = None;
+                return Action::Task(Task::perform(
+                    save(
+                        self.room.clone(),
+                        self.saved.clone(),
+                        self.edited.clone(),
+                    ),
+                    Message::Saved,
+                ));
+            }
+            Message::Saved(result) => {
+                self.saving = false;
+                match result {
+                    Ok(()) => {
+                        self.saved = self.edited.clone();
+                        self.status = Some(String::from("Saved"));
+                    }
+                    Err(error) => self.status = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let can = self.permissions;
+        let busy = self.saving || self.changing_avatar;
+
+        let picture = match &self.avatar {
+            Some(handle) => avatar::picture(handle, AVATAR_SIZE),
+            None => avatar::view(
+                self.room.room_id().as_str(),
+                &room_name(&self.room),
+                AVATAR_SIZE,
+            ),
+        };
+        let mut avatar_buttons = row![
+            button(text("Upload…").size(FONT_SIZE))
+                .style(button::secondary)
+                .on_press_maybe(
+                    (can.avatar && !busy).then_some(Message::ChangeAvatar)
+                )
+        ]
+        .spacing(10);
+        if self.room.avatar_url().is_some() {
+            avatar_buttons = avatar_buttons.push(
+                button(text("Remove").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press_maybe(
+                        (can.avatar && !busy).then_some(Message::RemoveAvatar),
+                    ),
+            );
+        }
+
+        let access = column(
+            [
+                (Access::Invite, "Invite only"),
+                (Access::Knock, "Ask to join"),
+                (Access::Public, "Anyone can join"),
+            ]
+            .into_iter()
+            .map(|(access, label)| {
+                radio(label, access, self.edited.access, |access| {
+                    Message::AccessSelected(access)
+                })
+                .size(14)
+                .text_size(FONT_SIZE)
+                .into()
+            }),
+        )
+        .spacing(6);
+        // Radios and pick lists can't be disabled, so they're only shown
+        // when usable
+        let access: Element<Message> = if can.join_rules {
+            access.into()
+        } else {
+            text(match self.edited.access {
+                Some(Access::Invite) => "Invite only",
+                Some(Access::Knock) => "Ask to join",
+                Some(Access::Public) => "Anyone can join",
+                None => "Restricted",
+            })
+            .size(FONT_SIZE)
+            .into()
+        };
+
+        let history: Element<Message> = if can.history {
+            pick_list(
+                History::ALL,
+                Some(self.edited.history),
+                Message::HistorySelected,
+            )
+            .text_size(FONT_SIZE)
+            .width(Length::Fill)
+            .into()
+        } else {
+            text(self.edited.history.to_string()).size(FONT_SIZE).into()
+        };
+
+        let mut aliases = Column::new().spacing(4);
+        for alias in self.edited.aliases() {
+            let is_main = self.edited.canonical_alias.as_ref() == Some(alias);
+            let mut line =
+                row![text(alias.as_str()).size(FONT_SIZE).width(Length::Fill)]
+                    .spacing(6)
+                    .align_y(Alignment::Center);
+            if is_main {
+                line = line.push(text("Main").size(FONT_SIZE - 2));
+            } else {
+                line = line.push(
+                    button(text("Make main").size(FONT_SIZE - 2))
+                        .style(button::text)
+                        .on_press_maybe(
+                            can.aliases
+                                .then(|| Message::MakeMainAlias(alias.clone())),
+                        ),
+                );
+            }
+            aliases = aliases.push(
+                line.push(
+                    button(text("Remove").size(FONT_SIZE - 2))
+                        .style(button::text)
+                        .on_press_maybe(
+                            can.aliases
+                                .then(|| Message::RemoveAlias(alias.clone())),
+                        ),
+                ),
+            );
+        }
+        let mut new_alias =
+            text_input("#alias:example.org", &self.new_alias).size(FONT_SIZE);
+        if can.aliases {
+            new_alias = new_alias
+                .on_input(Message::NewAliasInput)
+                .on_submit(Message::AddAlias);
+        }
+        aliases = aliases.push(
+            row![
+                new_alias,
+                button(text("Add").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press_maybe(
+                        (can.aliases && !self.new_alias.trim().is_empty())
+                            .then_some(Message::AddAlias)
+                    )
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+
+        let mut name =
+            text_input("Room name", &self.edited.name).size(FONT_SIZE);
+        if can.name {
+            name = name.on_input(Message::NameInput);
+        }
+        let mut topic = text_input("Topic", &self.edited.topic).size(FONT_SIZE);
+        if can.topic {
+            topic = topic.on_input(Message::TopicInput);
+        }
+
+        let fields = column![
+            field(
+                "Avatar:",
+                row![picture, avatar_buttons]
+                    .spacing(10)
+                    .align_y(Alignment::Center)
+            ),
+            field("Name:", name),
+            field("Topic:", topic),
+            field("Access:", access),
+            field(
+                "",
+                checkbox(self.edited.guest_access)
+                    .label("Allow guests to join")
+                    .on_toggle_maybe(
+                        can.guest_access.then_some(Message::ToggleGuestAccess)
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE)
+            ),
+            field("History:", history),
+            field(
+                "",
+                checkbox(self.edited.encrypted)
+                    .label("End-to-end encryption")
+                    .on_toggle_maybe(
+                        (can.encryption && !self.saved.encrypted)
+                            .then_some(Message::EnableEncryption)
+                    )
+                    .size(14)
+                    .text_size(FONT_SIZE)
+            ),
+            field("Aliases:", aliases),
+        ]
+        .spacing(12);
+
+        let mut footer = row![].spacing(10).align_y(Alignment::Center);
+        if let Some(status) = &self.status {
+            footer =
+                footer.push(text(status).size(FONT_SIZE).width(Length::Fill));
+        } else {
+            footer = footer.push(text("").width(Length::Fill));
+        }
+        if busy {
+            footer = footer.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        footer =
+            footer.push(button(text("Save").size(FONT_SIZE)).on_press_maybe(
+                (!busy && self.edited != self.saved).then_some(Message::Save),
+            ));
+
+        column![
+            row![
+                text("Room settings").size(20).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE)).on_press(Message::Close)
+            ]
+            .align_y(Alignment::Center),
+            rule::horizontal(1),
+            scrollable(fields).height(Length::Fill),
+            rule::horizontal(1),
+            footer
+        ]
+        .spacing(10)
+        .width(WIDTH)
+        .height(HEIGHT)
+        .into()
+    }
+
+    /// A full alias, or a local part completed with the user's server.
+    fn parse_alias(&self) -> Result<OwnedRoomAliasId, String> {
+        let alias = self.new_alias.trim().trim_start_matches('#');
+        let alias = if alias.contains(':') {
+            format!("#{alias}")
+        } else {
+            let server = self
+                .room
+                .client()
+                .user_id()
+                .map(|user_id| user_id.server_name().to_string())
+                .unwrap_or_default();
+            format!("#{alias}:{server}")
+        };
+        RoomAliasId::parse(&alias)
+            .map_err(|_error| format!("{alias} is not a valid alias"))
+    }
+}
+
+fn field<'a>(
+    label: &'a str,
+    input: impl Into<Element<'a, Message>>,
+) -> Element<'a, Message> {
+    row![text(label).size(FONT_SIZE).width(LABEL_WIDTH), input.into()]
+        .spacing(10)
+        .align_y(Alignment::Center)
+        .into()
+}
+
+async fn permissions(room: Room) -> Result<Permissions, String> {
+    let power_levels = room
+        .power_levels()
+        .await
+        .map_err(|error| error.to_string())?;
+    let Some(user_id) = room.client().user_id().map(ToOwned::to_owned) else {
+        return Ok(Permissions::default());
+    };
+    let can =
+        |event_type| power_levels.user_can_send_state(&user_id, event_type);
+
+    Ok(Permissions {
+        name: can(StateEventType::RoomName),
+        topic: can(StateEventType::RoomTopic),
+        avatar: can(StateEventType::RoomAvatar),
+        join_rules: can(StateEventType::RoomJoinRules),
+        guest_access: can(StateEventType::RoomGuestAccess),
+        history: can(StateEventType::RoomHistoryVisibility),
+        encryption: can(StateEventType::RoomEncryption),
+        aliases: can(StateEventType::RoomCanonicalAlias),
+    })
+}
+
+/// Asks for an image and sets it as the room's avatar. Returns false when
+/// the user cancels.
+async fn upload_avatar(room: Room) -> Result<bool, String> {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .add_filter("Images", &["png", "jpg", "jpeg", "gif", "webp"])
+        .pick_file()
+        .await
+    else {
+        return Ok(false);
+    };
+
+    let path = file.path().to_path_buf();
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let data = tokio::task::spawn_blocking(move || fs::read(path))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())?;
+    room.upload_avatar(&mime, data, None)
+        .await
+        .map_err(|error| error.to_string())?;
+    Ok(true)
+}
+
+/// Sends a state event for every setting that changed.
+async fn save(
+    room: Room,
+    saved: Settings,
+    edited: Settings,
+) -> Result<(), String> {
+    let error = |error: matrix_sdk::Error| error.to_string();
+
+    if edited.name != saved.name {
+        room.set_name(edited.name.trim().to_string())
+            .await
+            .map_err(error)?;
+    }
+    if edited.topic != saved.topic {
+        room.set_room_topic(edited.topic.trim())
+            .await
+            .map_err(error)?;
+    }
+    if edited.access != saved.access
+        && let Some(access) = edited.access
+    {
+        room.privacy_settings()
+            .update_join_rule(access.join_rule())
+            .await
+            .map_err(error)?;
+    }
+    if edited.guest_access != saved.guest_access {
+        room.send_state_event(RoomGuestAccessEventContent::new(
+            if edited.guest_access {
+                GuestAccess::CanJoin
+            } else {
+                GuestAccess::Forbidden
+            },
+        ))
+        .await
+        .map_err(error)?;
+    }
+    if edited.history != saved.history {
+        room.privacy_settings()
+            .update_room_history_visibility(edited.history.visibility())
+            .await
+            .map_err(error)?;
+    }
+    if edited.encrypted && !saved.encrypted {
+        room.enable_encryption().await.map_err(error)?;
+    }
+
+    if edited.canonical_alias != saved.canonical_alias
+        || edited.alt_aliases != saved.alt_aliases
+    {
+        // New aliases have to point at the room before they can be published
+        for alias in edited.aliases() {
+            if saved.aliases().any(|a| a == alias) {
+                continue;
+            }
+            let created = room
+                .privacy_settings()
+                .publish_room_alias_in_room_directory(alias)
+                .await
+                .map_err(error)?;
+            if !created {
+                let resolved = room
+                    .client()
+                    .resolve_room_alias(alias)
+                    .await
+                    .map_err(|error| error.to_string())?;
+                if resolved.room_id != room.room_id() {
+                    return Err(format!("{alias} is already in use"));
+                }
+            }
+        }
+        room.privacy_settings()
+            .update_canonical_alias(edited.canonical_alias, edited.alt_aliases)
+            .await
+            .map_err(error)?;
+    }
+
+    Ok(())
+}
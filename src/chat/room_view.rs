@@ -0,0 +1,1879 @@
+use crate::chat::avatar;
+use crate::chat::edit_history;
+use crate::chat::edit_history::EditHistory;
+use crate::chat::format_date;
+use crate::chat::html;
+use crate::chat::media;
+use crate::chat::media::MediaCache;
+use crate::chat::moderation;
+use crate::chat::search::SearchIndex;
+use crate::chat::upload;
+use crate::chat::upload::Attachment;
+use crate::loading_spinner::Spinner;
+use crate::modal;
+use crate::modal::modal;
+use crate::typing_indicator::TypingIndicator;
+use iced::Alignment;
+use iced::ContentFit;
+use iced::Element;
+use iced::Font;
+use iced::Length;
+use iced::Task;
+use iced::Theme;
+use iced::font;
+use iced::futures::Stream;
+use iced::futures::StreamExt;
+use iced::futures::stream;
+use iced::task;
+use iced::widget::Column;
+use iced::widget::Row;
+use iced::widget::button;
+use iced::widget::center;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::hover;
+use iced::widget::image;
+use iced::widget::progress_bar;
+use iced::widget::right;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::sensor;
+use iced::widget::stack;
+use iced::widget::text;
+use iced::widget::text_input;
+use iced::widget::tooltip;
+use matrix_sdk::Room;
+use matrix_sdk::deserialized_responses::VerificationLevel;
+use matrix_sdk::deserialized_responses::VerificationState;
+use matrix_sdk::room::Receipts;
+use matrix_sdk::room::edit::EditedContent;
+use matrix_sdk::ruma::DeviceId;
+use matrix_sdk::ruma::OwnedEventId;
+use matrix_sdk::ruma::OwnedMxcUri;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::UInt;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::api::client::error::ErrorKind;
+use matrix_sdk::ruma::api::client::receipt::create_receipt::v3::ReceiptType;
+use matrix_sdk::ruma::events::room::MediaSource;
+use matrix_sdk::ruma::events::room::message::ImageMessageEventContent;
+use matrix_sdk::ruma::events::room::message::MessageFormat;
+use matrix_sdk::ruma::events::room::message::MessageType;
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContentWithoutRelation;
+use matrix_sdk::ruma::events::room::power_levels::RoomPowerLevels;
+use matrix_sdk_crypto::types::events::UtdCause;
+use matrix_sdk_ui::Timeline;
+use matrix_sdk_ui::eyeball_im::Vector;
+use matrix_sdk_ui::eyeball_im::VectorDiff;
+use matrix_sdk_ui::timeline::EncryptedMessage;
+use matrix_sdk_ui::timeline::EventTimelineItem;
+use matrix_sdk_ui::timeline::InReplyToDetails;
+use matrix_sdk_ui::timeline::MsgLikeKind;
+use matrix_sdk_ui::timeline::Profile;
+use matrix_sdk_ui::timeline::ReactionsByKeyBySender;
+use matrix_sdk_ui::timeline::TimelineDetails;
+use matrix_sdk_ui::timeline::TimelineEventItemId;
+use matrix_sdk_ui::timeline::TimelineItem;
+use matrix_sdk_ui::timeline::TimelineItemContent;
+use matrix_sdk_ui::timeline::TimelineItemKind;
+use matrix_sdk_ui::timeline::VirtualTimelineItem;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const PAGINATION_SIZE: u16 = 30;
+const PREVIEW_LENGTH: usize = 120;
+const DIALOG_WIDTH: f32 = 350.0;
+const MAX_RECEIPT_AVATARS: usize = 3;
+const RECEIPT_AVATAR_SIZE: f32 = 16.0;
+const ATTACHMENT_PREVIEW_SIZE: f32 = 48.0;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    OpenThread(OwnedEventId),
+    PickReaction(TimelineEventItemId),
+    Moderate(OwnedUserId, String, moderation::Kind),
+}
+
+pub struct RoomView {
+    room: Room,
+    timeline: Arc<Timeline>,
+    items: Vector<Arc<TimelineItem>>,
+    paginating: bool,
+    composer: String,
+    replying_to: Option<ReplyTo>,
+    editing: Option<TimelineEventItemId>,
+    redacting: Option<Redaction>,
+    edit_history: Option<EditHistory>,
+    power_levels: Option<RoomPowerLevels>,
+    // Replied-to events we already asked the server about
+    fetched_details: HashSet<OwnedEventId>,
+    media: MediaCache,
+    search_index: SearchIndex,
+    thumbnails: HashMap<OwnedMxcUri, Thumbnail>,
+    // Files dropped onto the room, sent along with the next message
+    attachments: Vec<Attachment>,
+    files_hovered: bool,
+    // Other users currently typing in the room
+    typing: Vec<OwnedUserId>,
+    // The newest event we sent a read receipt for
+    read_up_to: Option<OwnedEventId>,
+    // Megolm sessions we looked for in the key backup
+    backup_lookups: HashMap<String, BackupLookup>,
+    // Whether we verified the senders of messages from unsigned sessions
+    verified_senders: HashMap<OwnedUserId, bool>,
+    error: Option<String>,
+    // Dropping the view stops listening to the timeline and typing notices
+    _updates: task::Handle,
+}
+
+/// How far the sender of an event in an encrypted room can be trusted.
+#[derive(Debug, Clone, Copy)]
+enum Shield {
+    Verified,
+    // A session its owner hasn't verified, of a user we may have verified
+    UnverifiedDevice,
+    // A session we don't know or can't link to the sender
+    UnknownDevice,
+    IdentityChanged,
+    Unencrypted,
+}
+
+/// A snapshot of the event being replied to, shown above the composer.
+struct ReplyTo {
+    event_id: OwnedEventId,
+    sender: String,
+    preview: String,
+}
+
+enum Thumbnail {
+    Loading,
+    Loaded(image::Handle),
+    Error,
+}
+
+/// How looking for a message's keys in the key backup went.
+enum BackupLookup {
+    Searching,
+    NotFound,
+}
+
+/// A pending deletion, waiting for the user to confirm it.
+struct Redaction {
+    item_id: TimelineEventItemId,
+    reason: String,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Diffs(Vec<VectorDiff<Arc<TimelineItem>>>),
+    PaginateBackwards,
+    Paginated(Result<bool, String>),
+    DetailsFetched(Result<(), String>),
+    LinkClicked(html::Link),
+    ComposerInput(String),
+    Send,
+    Sent(Result<(), String>),
+    Reply(OwnedEventId),
+    CancelReply,
+    OpenThread(OwnedEventId),
+    PowerLevels(Option<RoomPowerLevels>),
+    Edit(TimelineEventItemId),
+    CancelEdit,
+    Redact(TimelineEventItemId),
+    RedactReasonInput(String),
+    ConfirmRedact,
+    CancelRedact,
+    Redacted(Result<(), String>),
+    ShowEditHistory(OwnedEventId),
+    EditHistoryLoaded(Result<Vec<edit_history::Revision>, String>),
+    CloseEditHistory,
+    PickReaction(TimelineEventItemId),
+    ToggleReaction(TimelineEventItemId, String),
+    ReactionToggled(Result<(), String>),
+    Typing(Vec<OwnedUserId>),
+    TypingNoticeSent(Result<(), String>),
+    EventShown(OwnedEventId),
+    ReceiptsSent(Result<(), String>),
+    ThumbnailLoaded(OwnedMxcUri, Result<image::Handle, String>),
+    SenderIdentityLoaded(OwnedUserId, bool),
+    Download(MediaSource, String),
+    Downloaded(Result<Option<PathBuf>, String>),
+    FilesHovered(bool),
+    FileDropped(PathBuf),
+    CaptionInput(PathBuf, String),
+    RemoveAttachment(PathBuf),
+    Upload(PathBuf, upload::Update),
+    Moderate(OwnedUserId, String, moderation::Kind),
+    CheckBackup(String),
+    BackupChecked(String, Result<bool, String>),
+}
+
+impl RoomView {
+    pub fn new(
+        room: Room,
+        timeline: Arc<Timeline>,
+        media: MediaCache,
+        search_index: SearchIndex,
+    ) -> (Self, Task<Message>) {
+        let diffs =
+            Task::run(timeline_updates(timeline.clone()), Message::Diffs);
+        // Typing notices are per room, so only the main timeline shows them
+        let (updates, handle) = if timeline.is_threaded() {
+            diffs.abortable()
+        } else {
+            let typing =
+                Task::run(typing_updates(room.clone()), Message::Typing);
+            Task::batch([diffs, typing]).abortable()
+        };
+        let power_levels =
+            Task::perform(power_levels(room.clone()), Message::PowerLevels);
+
+        (
+            Self {
+                room,
+                timeline,
+                items: Vector::new(),
+                paginating: false,
+                composer: String::new(),
+                replying_to: None,
+                editing: None,
+                redacting: None,
+                edit_history: None,
+                power_levels: None,
+                fetched_details: HashSet::new(),
+                media,
+                search_index,
+                thumbnails: HashMap::new(),
+                attachments: Vec::new(),
+                files_hovered: false,
+                typing: Vec::new(),
+                read_up_to: None,
+                backup_lookups: HashMap::new(),
+                verified_senders: HashMap::new(),
+                error: None,
+                _updates: handle.abort_on_drop(),
+            },
+            Task::batch([updates, power_levels]),
+        )
+    }
+
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Diffs(diffs) => {
+                let mut changed = Vec::new();
+                for diff in diffs {
+                    match &diff {
+                        VectorDiff::Append { values }
+                        | VectorDiff::Reset { values } => {
+                            changed.extend(values.iter().cloned());
+                        }
+                        VectorDiff::PushFront { value }
+                        | VectorDiff::PushBack { value }
+                        | VectorDiff::Insert { value, .. }
+                        | VectorDiff::Set { value, .. } => {
+                            changed.push(value.clone());
+                        }
+                        _ => (),
+                    }
+                    diff.apply(&mut self.items);
+                }
+                self.index_messages(&changed);
+                return Action::Task(Task::batch([
+                    self.fetch_missing_replies(),
+                    self.load_thumbnails(),
+                    self.load_sender_identities(),
+                ]));
+            }
+            Message::PaginateBackwards => {
+                if self.paginating {
+                    return Action::None;
+                }
+                self.paginating = true;
+                return Action::Task(Task::perform(
+                    paginate_backwards(self.timeline.clone()),
+                    Message::Paginated,
+                ));
+            }
+            Message::Paginated(result) => {
+                self.paginating = false;
+                self.error = result.err();
+            }
+            Message::DetailsFetched(_result) => (),
+            Message::LinkClicked(link) => {
+                let _ = open::that_detached(link.url());
+            }
+            Message::ComposerInput(string) => {
+                let typing = !string.trim().is_empty();
+                self.composer = string;
+                return Action::Task(Task::perform(
+                    typing_notice(self.room.clone(), typing),
+                    Message::TypingNoticeSent,
+                ));
+            }
+            Message::Send => {
+                let mut tasks = self.start_uploads();
+                if self.composer.trim().is_empty() {
+                    return Action::Task(Task::batch(tasks));
+                }
+                let body = std::mem::take(&mut self.composer);
+                tasks.push(if let Some(item_id) = self.editing.take() {
+                    Task::perform(
+                        edit_message(self.timeline.clone(), item_id, body),
+                        Message::Sent,
+                    )
+                } else {
+                    let in_reply_to =
+                        self.replying_to.take().map(|reply| reply.event_id);
+                    Task::perform(
+                        send_message(self.timeline.clone(), body, in_reply_to),
+                        Message::Sent,
+                    )
+                });
+                tasks.push(Task::perform(
+                    typing_notice(self.room.clone(), false),
+                    Message::TypingNoticeSent,
+                ));
+                return Action::Task(Task::batch(tasks));
+            }
+            Message::Sent(result) => {
+                self.error = result.err();
+            }
+            Message::Reply(event_id) => {
+                let Some(event) = self.event(&event_id) else {
+                    return Action::None;
+                };
+                self.replying_to = Some(ReplyTo {
+                    sender: sender_name(event.sender(), event.sender_profile()),
+                    preview: preview(event.content()),
+                    event_id,
+                });
+                if self.editing.take().is_some() {
+                    self.composer.clear();
+                }
+            }
+            Message::CancelReply => {
+                self.replying_to = None;
+            }
+            Message::OpenThread(event_id) => {
+                return Action::OpenThread(event_id);
+            }
+            Message::PowerLevels(power_levels) => {
+                self.power_levels = power_levels;
+            }
+            Message::Edit(item_id) => {
+                let Some(message) = self
+                    .event_by_item_id(&item_id)
+                    .and_then(|event| event.content().as_message())
+                else {
+                    return Action::None;
+                };
+                self.composer = message.body().to_string();
+                self.editing = Some(item_id);
+                self.replying_to = None;
+            }
+            Message::CancelEdit => {
+                self.editing = None;
+                self.composer.clear();
+            }
+            Message::Moderate(user_id, name, kind) => {
+                return Action::Moderate(user_id, name, kind);
+            }
+            Message::CheckBackup(session_id) => {
+                if self.backup_lookups.contains_key(&session_id) {
+                    return Action::None;
+                }
+                self.backup_lookups
+                    .insert(session_id.clone(), BackupLookup::Searching);
+                return Action::Task(Task::perform(
+                    check_backup(
+                        self.room.clone(),
+                        self.timeline.clone(),
+                        session_id.clone(),
+                    ),
+                    move |result| Message::BackupChecked(session_id, result),
+                ));
+            }
+            Message::BackupChecked(session_id, result) => match result {
+                // The message decrypts and replaces the notice
+                Ok(true) => {
+                    self.backup_lookups.remove(&session_id);
+                }
+                Ok(false) => {
+                    self.backup_lookups
+                        .insert(session_id, BackupLookup::NotFound);
+                }
+                Err(error) => {
+                    self.backup_lookups.remove(&session_id);
+                    self.error =
+                        Some(format!("Couldn't check the key backup: {error}"));
+                }
+            },
+            Message::Redact(item_id) => {
+                self.redacting = Some(Redaction {
+                    item_id,
+                    reason: String::new(),
+                });
+            }
+            Message::RedactReasonInput(string) => {
+                if let Some(redaction) = &mut self.redacting {
+                    redaction.reason = string;
+                }
+            }
+            Message::ConfirmRedact => {
+                let Some(redaction) = self.redacting.take() else {
+                    return Action::None;
+                };
+                let reason = Some(redaction.reason)
+                    .filter(|reason| !reason.trim().is_empty());
+                return Action::Task(Task::perform(
+                    redact(self.timeline.clone(), redaction.item_id, reason),
+                    Message::Redacted,
+                ));
+            }
+            Message::CancelRedact => {
+                self.redacting = None;
+            }
+            Message::Redacted(result) => {
+                self.error = result.err();
+            }
+            Message::ShowEditHistory(event_id) => {
+                let Some(event) = self.event(&event_id) else {
+                    return Action::None;
+                };
+                let Some(original) = edit_history::original(event) else {
+                    return Action::None;
+                };
+                let sender = event.sender().to_owned();
+                self.edit_history = Some(EditHistory::Loading);
+                return Action::Task(Task::perform(
+                    edit_history::load(
+                        self.room.clone(),
+                        event_id,
+                        sender,
+                        original,
+                    ),
+                    Message::EditHistoryLoaded,
+                ));
+            }
+            Message::EditHistoryLoaded(result) => {
+                if self.edit_history.is_some() {
+                    self.edit_history = Some(match result {
+                        Ok(revisions) => EditHistory::Loaded(revisions),
+                        Err(error) => EditHistory::Error(error),
+                    });
+                }
+            }
+            Message::CloseEditHistory => {
+                self.edit_history = None;
+            }
+            Message::PickReaction(item_id) => {
+                return Action::PickReaction(item_id);
+            }
+            Message::ToggleReaction(item_id, key) => {
+                return Action::Task(Task::perform(
+                    toggle_reaction(self.timeline.clone(), item_id, key),
+                    Message::ReactionToggled,
+                ));
+            }
+            Message::ReactionToggled(result) => {
+                self.error = result.err();
+            }
+            Message::Typing(user_ids) => {
+                self.typing = user_ids;
+            }
+            Message::TypingNoticeSent(_result) => (),
+            Message::EventShown(event_id) => {
+                if !self.is_unread(&event_id) {
+                    return Action::None;
+                }
+                self.read_up_to = Some(event_id.clone());
+                return Action::Task(Task::perform(
+                    send_receipts(self.timeline.clone(), event_id),
+                    Message::ReceiptsSent,
+                ));
+            }
+            Message::ReceiptsSent(_result) => (),
+            Message::ThumbnailLoaded(uri, result) => {
+                let thumbnail = match result {
+                    Ok(handle) => Thumbnail::Loaded(handle),
+                    Err(_error) => Thumbnail::Error,
+                };
+                self.thumbnails.insert(uri, thumbnail);
+            }
+            Message::SenderIdentityLoaded(user_id, is_verified) => {
+                self.verified_senders.insert(user_id, is_verified);
+            }
+            Message::Download(source, file_name) => {
+                return Action::Task(Task::perform(
+                    self.media.clone().save(
+                        self.room.client(),
+                        source,
+                        file_name,
+                    ),
+                    Message::Downloaded,
+                ));
+            }
+            Message::Downloaded(result) => {
+                self.error = result.err();
+            }
+            Message::FilesHovered(hovered) => {
+                self.files_hovered = hovered;
+            }
+            Message::FileDropped(path) => {
+                self.files_hovered = false;
+                if self.attachment_mut(&path).is_some() {
+                    return Action::None;
+                }
+                match Attachment::new(path) {
+                    Ok(attachment) => self.attachments.push(attachment),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::CaptionInput(path, caption) => {
+                if let Some(attachment) = self.attachment_mut(&path) {
+                    attachment.caption = caption;
+                }
+            }
+            Message::RemoveAttachment(path) => {
+                self.attachments
+                    .retain(|attachment| attachment.path != path);
+            }
+            Message::Upload(path, update) => {
+                let Some(attachment) = self.attachment_mut(&path) else {
+                    return Action::None;
+                };
+                match update {
+                    upload::Update::Progress(progress) => {
+                        attachment.progress = Some(progress);
+                    }
+                    upload::Update::Finished(Ok(())) => {
+                        self.attachments
+                            .retain(|attachment| attachment.path != path);
+                    }
+                    upload::Update::Finished(Err(error)) => {
+                        attachment.reset();
+                        self.error = Some(error);
+                    }
+                }
+            }
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut items: Vec<Element<Message>> = Vec::new();
+
+        let at_start = self.items.front().is_some_and(|item| {
+            matches!(
+                item.as_virtual(),
+                Some(VirtualTimelineItem::TimelineStart)
+            )
+        });
+        if !at_start {
+            items.push(if self.paginating {
+                center_x(
+                    Spinner::new()
+                        .size(20.0)
+                        .bar_height(2.0)
+                        .cycle_duration(Duration::from_secs_f32(1.0)),
+                )
+                .into()
+            } else {
+                center_x(
+                    button(text("Load older messages").size(FONT_SIZE))
+                        .on_press(Message::PaginateBackwards),
+                )
+                .into()
+            });
+        }
+
+        for item in &self.items {
+            match item.kind() {
+                TimelineItemKind::Event(event) => {
+                    let content = self.view_event(event);
+                    // Seeing an event marks everything up to it as read
+                    items.push(match event.event_id() {
+                        Some(event_id) => sensor(content)
+                            .on_show(move |_size| {
+                                Message::EventShown(event_id.to_owned())
+                            })
+                            .key(event_id.to_owned())
+                            .into(),
+                        None => content,
+                    });
+                }
+                TimelineItemKind::Virtual(
+                    VirtualTimelineItem::DateDivider(timestamp),
+                ) => {
+                    let date = format_date(*timestamp);
+                    items.push(
+                        row![
+                            rule::horizontal(1),
+                            text(date).size(FONT_SIZE - 2),
+                            rule::horizontal(1)
+                        ]
+                        .spacing(10)
+                        .align_y(Alignment::Center)
+                        .into(),
+                    );
+                }
+                TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker) => {
+                    items.push(rule::horizontal(1).into());
+                }
+                TimelineItemKind::Virtual(
+                    VirtualTimelineItem::TimelineStart,
+                ) => (),
+            }
+        }
+
+        let timeline = scrollable(
+            Column::with_children(items)
+                .spacing(10)
+                .padding(10)
+                .width(Length::Fill),
+        )
+        .anchor_bottom()
+        .height(Length::Fill);
+
+        let content = column![timeline, self.view_composer()];
+        let content: Element<Message> = if self.files_hovered {
+            stack![
+                content,
+                center(
+                    container(
+                        text("Drop files to attach them").size(FONT_SIZE + 2)
+                    )
+                    .padding(20)
+                    .style(modal::style)
+                )
+                .style(modal::backdrop)
+            ]
+            .into()
+        } else {
+            content.into()
+        };
+
+        if let Some(redaction) = &self.redacting {
+            modal(content, view_redaction(redaction), Message::CancelRedact)
+        } else if let Some(edit_history) = &self.edit_history {
+            modal(
+                content,
+                edit_history.view(Message::CloseEditHistory),
+                Message::CloseEditHistory,
+            )
+        } else {
+            content
+        }
+    }
+
+    fn view_event<'a>(
+        &'a self,
+        event: &'a EventTimelineItem,
+    ) -> Element<'a, Message> {
+        let mut header = row![
+            text(sender_name(event.sender(), event.sender_profile()))
+                .size(FONT_SIZE)
+                .font(BOLD)
+        ]
+        .spacing(6)
+        .align_y(Alignment::Center);
+        if let Some(shield) = self.shield(event) {
+            let device_id = event
+                .encryption_info()
+                .and_then(|info| info.sender_device.as_deref());
+            header = header.push(view_shield(shield, device_id));
+        }
+        let mut content: Vec<Element<Message>> = vec![header.into()];
+
+        let msglike = event.content().as_msglike();
+        if let Some(in_reply_to) = msglike.and_then(|m| m.in_reply_to.as_ref())
+        {
+            content.push(view_in_reply_to(in_reply_to));
+        }
+        content.push(self.view_content(event.content()));
+
+        let edited =
+            event.content().as_message().is_some_and(|m| m.is_edited());
+        if let (true, Some(event_id)) = (edited, event.event_id()) {
+            content.push(
+                button(text("(edited)").size(FONT_SIZE - 2))
+                    .style(button::text)
+                    .padding(0)
+                    .on_press(Message::ShowEditHistory(event_id.to_owned()))
+                    .into(),
+            );
+        }
+
+        if let Some(reactions) = event.content().reactions()
+            && !reactions.is_empty()
+        {
+            content.push(self.view_reactions(event, reactions));
+        }
+
+        let thread_summary = msglike.and_then(|m| m.thread_summary.as_ref());
+        if let (Some(summary), Some(event_id)) =
+            (thread_summary, event.event_id())
+        {
+            let replies = match summary.num_replies {
+                1 => String::from("1 reply"),
+                count => format!("{count} replies"),
+            };
+            content.push(
+                button(text(replies).size(FONT_SIZE - 2))
+                    .style(button::text)
+                    .padding(0)
+                    .on_press(Message::OpenThread(event_id.to_owned()))
+                    .into(),
+            );
+        }
+
+        if let Some(receipts) = self.view_read_receipts(event) {
+            content.push(receipts);
+        }
+
+        let message = Column::with_children(content)
+            .spacing(2)
+            .width(Length::Fill);
+
+        let mut actions = row![].spacing(5);
+        if let Some(event_id) = event.event_id() {
+            if event.can_be_replied_to() {
+                actions = actions.push(action_button(
+                    "Reply",
+                    Message::Reply(event_id.to_owned()),
+                ));
+            }
+            if !self.timeline.is_threaded() && msglike.is_some() {
+                actions = actions.push(action_button(
+                    "Thread",
+                    Message::OpenThread(event_id.to_owned()),
+                ));
+            }
+        }
+        if msglike.is_some() && !event.content().is_redacted() {
+            actions = actions.push(action_button(
+                "React",
+                Message::PickReaction(event.identifier()),
+            ));
+        }
+        if event.is_editable() {
+            actions = actions
+                .push(action_button("Edit", Message::Edit(event.identifier())));
+        }
+        if self.can_redact(event) {
+            actions = actions.push(action_button(
+                "Delete",
+                Message::Redact(event.identifier()),
+            ));
+        }
+        for kind in self.moderation_kinds(event) {
+            actions = actions.push(action_button(
+                kind.label(),
+                Message::Moderate(
+                    event.sender().to_owned(),
+                    sender_name(event.sender(), event.sender_profile()),
+                    kind,
+                ),
+            ));
+        }
+
+        hover(message, right(actions))
+    }
+
+    fn view_content<'a>(
+        &'a self,
+        content: &'a TimelineItemContent,
+    ) -> Element<'a, Message> {
+        let Some(msglike) = content.as_msglike() else {
+            return text("Unsupported event").size(FONT_SIZE - 2).into();
+        };
+
+        match &msglike.kind {
+            MsgLikeKind::Message(message) => match message.msgtype() {
+                MessageType::Image(image) => self.view_image(image),
+                MessageType::File(file) => view_file(
+                    file.filename(),
+                    file.info.as_ref().and_then(|info| info.size),
+                    &file.source,
+                ),
+                MessageType::Video(video) => view_file(
+                    video.filename(),
+                    video.info.as_ref().and_then(|info| info.size),
+                    &video.source,
+                ),
+                MessageType::Audio(audio) => view_file(
+                    audio.filename(),
+                    audio.info.as_ref().and_then(|info| info.size),
+                    &audio.source,
+                ),
+                msgtype => view_body(msgtype),
+            },
+            MsgLikeKind::Redacted => {
+                text("Message deleted").size(FONT_SIZE - 2).into()
+            }
+            MsgLikeKind::UnableToDecrypt(encrypted) => {
+                self.view_undecryptable(encrypted)
+            }
+            _ => text("Unsupported event").size(FONT_SIZE - 2).into(),
+        }
+    }
+
+    /// A placeholder for an event we have no keys for, explaining why and
+    /// offering to ask for the keys when they might still arrive. The
+    /// timeline decrypts and replaces it by itself once they do.
+    fn view_undecryptable<'a>(
+        &'a self,
+        encrypted: &'a EncryptedMessage,
+    ) -> Element<'a, Message> {
+        let mut content = column![
+            text("Unable to decrypt message")
+                .size(FONT_SIZE - 1)
+                .font(BOLD)
+        ]
+        .spacing(4);
+
+        #[allow(deprecated)]
+        if let EncryptedMessage::MegolmV1AesSha2 {
+            session_id, cause, ..
+        } = encrypted
+        {
+            content =
+                content.push(text(utd_explanation(*cause)).size(FONT_SIZE - 2));
+            match self.backup_lookups.get(session_id) {
+                Some(BackupLookup::Searching) => {
+                    content = content.push(
+                        text("Looking in the key backup...")
+                            .size(FONT_SIZE - 2),
+                    );
+                }
+                Some(BackupLookup::NotFound) => {
+                    content = content.push(
+                        text(
+                            "The keys aren't in the key backup either. The \
+                             message appears if they reach this session later.",
+                        )
+                        .size(FONT_SIZE - 2),
+                    );
+                }
+                None if may_be_in_backup(*cause) => {
+                    content = content.push(action_button(
+                        "Check key backup",
+                        Message::CheckBackup(session_id.clone()),
+                    ));
+                }
+                None => (),
+            }
+        }
+
+        container(content)
+            .padding([4, 8])
+            .style(container::rounded_box)
+            .into()
+    }
+
+    /// The thumbnail scaled to fit [`media::THUMBNAIL_SIZE`], followed by the
+    /// file name and a download button.
+    fn view_image<'a>(
+        &'a self,
+        content: &'a ImageMessageEventContent,
+    ) -> Element<'a, Message> {
+        let info = content.info.as_deref();
+        let (width, height) = info
+            .and_then(|info| Some((info.width?, info.height?)))
+            .map(|(width, height)| {
+                fit_thumbnail(u64::from(width) as f32, u64::from(height) as f32)
+            })
+            .unwrap_or((media::THUMBNAIL_SIZE as f32, 200.0));
+
+        let source = thumbnail_source(content);
+        let preview: Element<Message> =
+            match self.thumbnails.get(media_uri(&source)) {
+                Some(Thumbnail::Loaded(handle)) => {
+                    image(handle).width(width).height(height).into()
+                }
+                Some(Thumbnail::Error) => {
+                    container(text("Could not load image").size(FONT_SIZE - 2))
+                        .center_x(width)
+                        .center_y(height)
+                        .style(container::rounded_box)
+                        .into()
+                }
+                _ => container(
+                    Spinner::new()
+                        .size(20.0)
+                        .bar_height(2.0)
+                        .cycle_duration(Duration::from_secs_f32(1.0)),
+                )
+                .center_x(width)
+                .center_y(height)
+                .style(container::rounded_box)
+                .into(),
+            };
+
+        column![
+            preview,
+            view_file(
+                content.filename(),
+                info.and_then(|info| info.size),
+                &content.source,
+            )
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    /// One chip per reaction key, highlighted when we reacted with it.
+    fn view_reactions<'a>(
+        &'a self,
+        event: &'a EventTimelineItem,
+        reactions: &'a ReactionsByKeyBySender,
+    ) -> Element<'a, Message> {
+        let own_user_id = self.room.own_user_id();
+        let mut chips = Row::new().spacing(5);
+        for (key, senders) in reactions.iter() {
+            let reacted = senders.contains_key(own_user_id);
+            let who = senders
+                .keys()
+                .map(|sender| self.member_name(sender))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            chips = chips.push(tooltip(
+                button(
+                    text(format!("{key} {}", senders.len())).size(FONT_SIZE),
+                )
+                .style(if reacted {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .padding([2, 6])
+                .on_press(Message::ToggleReaction(
+                    event.identifier(),
+                    key.clone(),
+                )),
+                container(
+                    text(format!("{who} reacted with {key}"))
+                        .size(FONT_SIZE - 2),
+                )
+                .padding(5)
+                .style(container::rounded_box),
+                tooltip::Position::Top,
+            ));
+        }
+        chips = chips.push(
+            button(text("+").size(FONT_SIZE))
+                .style(button::secondary)
+                .padding([2, 6])
+                .on_press(Message::PickReaction(event.identifier())),
+        );
+
+        chips.wrap().into()
+    }
+
+    /// Avatars of the other users whose latest read receipt is on this event,
+    /// collapsed into a count after the first few.
+    fn view_read_receipts(
+        &self,
+        event: &EventTimelineItem,
+    ) -> Option<Element<'_, Message>> {
+        let own_user_id = self.room.own_user_id();
+        let readers: Vec<&OwnedUserId> = event
+            .read_receipts()
+            .keys()
+            .filter(|user_id| *user_id != own_user_id)
+            .collect();
+        if readers.is_empty() {
+            return None;
+        }
+        let names: Vec<String> = readers
+            .iter()
+            .map(|user_id| self.member_name(user_id))
+            .collect();
+
+        let mut avatars = Row::new().spacing(2).align_y(Alignment::Center);
+        for (user_id, name) in
+            readers.iter().zip(&names).take(MAX_RECEIPT_AVATARS)
+        {
+            avatars = avatars.push(avatar::view(
+                user_id.as_str(),
+                name,
+                RECEIPT_AVATAR_SIZE,
+            ));
+        }
+        if readers.len() > MAX_RECEIPT_AVATARS {
+            avatars = avatars.push(
+                text(format!("+{}", readers.len() - MAX_RECEIPT_AVATARS))
+                    .size(FONT_SIZE - 3),
+            );
+        }
+
+        Some(
+            right(tooltip(
+                avatars,
+                container(
+                    text(format!("Seen by {}", names.join(", ")))
+                        .size(FONT_SIZE - 2),
+                )
+                .padding(5)
+                .style(container::rounded_box),
+                tooltip::Position::Top,
+            ))
+            .into(),
+        )
+    }
+
+    /// "Alice is typing…", "Alice and Bob are typing…" and so on.
+    fn typing_text(&self) -> String {
+        let names: Vec<String> = self
+            .typing
+            .iter()
+            .map(|user_id| self.member_name(user_id))
+            .collect();
+        match names.as_slice() {
+            [] => String::new(),
+            [name] => format!("{name} is typing…"),
+            [first, second] => format!("{first} and {second} are typing…"),
+            [first, second, third] => {
+                format!("{first}, {second} and {third} are typing…")
+            }
+            [first, second, rest @ ..] => format!(
+                "{first}, {second} and {} others are typing…",
+                rest.len()
+            ),
+        }
+    }
+
+    /// A display name for a user, taken from their messages in the timeline.
+    fn member_name(&self, user_id: &UserId) -> String {
+        self.items
+            .iter()
+            .filter_map(|item| item.as_event())
+            .find(|event| event.sender() == user_id)
+            .map(|event| sender_name(user_id, event.sender_profile()))
+            .unwrap_or_else(|| user_id.to_string())
+    }
+
+    fn view_composer(&self) -> Element<'_, Message> {
+        let mut composer = column![].spacing(5).padding(10);
+
+        if !self.typing.is_empty() {
+            composer = composer.push(
+                row![
+                    TypingIndicator::new(),
+                    text(self.typing_text()).size(FONT_SIZE - 2)
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+
+        if let Some(error) = &self.error {
+            composer = composer.push(text(error).size(FONT_SIZE));
+        }
+
+        for attachment in &self.attachments {
+            composer = composer.push(view_attachment(attachment));
+        }
+
+        if self.editing.is_some() {
+            composer = composer.push(
+                row![
+                    text("Editing message")
+                        .size(FONT_SIZE - 2)
+                        .font(BOLD)
+                        .width(Length::Fill),
+                    action_button("Cancel", Message::CancelEdit)
+                ]
+                .spacing(5)
+                .align_y(Alignment::Center),
+            );
+        }
+
+        if let Some(reply) = &self.replying_to {
+            composer = composer.push(
+                row![
+                    quote(
+                        column![
+                            text(format!("Replying to {}", reply.sender))
+                                .size(FONT_SIZE - 2)
+                                .font(BOLD),
+                            text(&reply.preview).size(FONT_SIZE - 2)
+                        ]
+                        .width(Length::Fill)
+                    ),
+                    action_button("Cancel", Message::CancelReply)
+                ]
+                .spacing(5),
+            );
+        }
+
+        let placeholder = if self.editing.is_some() {
+            "Edit message"
+        } else if self.timeline.is_threaded() {
+            "Reply in thread"
+        } else {
+            "Send a message"
+        };
+
+        composer
+            .push(
+                row![
+                    text_input(placeholder, &self.composer)
+                        .on_input(Message::ComposerInput)
+                        .on_submit(Message::Send)
+                        .size(FONT_SIZE),
+                    button(text("Send").size(FONT_SIZE))
+                        .on_press(Message::Send)
+                ]
+                .spacing(10)
+                .align_y(Alignment::Center),
+            )
+            .into()
+    }
+
+    /// Adds the decrypted messages among the new or changed timeline `items`
+    /// to the search index, including ones loaded by paginating and ones
+    /// decrypted late.
+    fn index_messages(&self, items: &[Arc<TimelineItem>]) {
+        for item in items {
+            let Some(event) = item.as_event() else {
+                continue;
+            };
+            let (Some(event_id), Some(message)) =
+                (event.event_id(), event.content().as_message())
+            else {
+                continue;
+            };
+            self.search_index.insert(
+                self.room.room_id().to_owned(),
+                event_id.to_owned(),
+                event.sender().to_owned(),
+                event.timestamp(),
+                message.body(),
+            );
+        }
+    }
+
+    fn event(&self, event_id: &OwnedEventId) -> Option<&EventTimelineItem> {
+        self.items
+            .iter()
+            .filter_map(|item| item.as_event())
+            .find(|event| event.event_id() == Some(event_id))
+    }
+
+    fn event_by_item_id(
+        &self,
+        item_id: &TimelineEventItemId,
+    ) -> Option<&EventTimelineItem> {
+        self.items
+            .iter()
+            .filter_map(|item| item.as_event())
+            .find(|event| event.identifier() == *item_id)
+    }
+
+    /// The shield shown next to the sender of a message in an encrypted room.
+    /// Senders whose identity we simply haven't verified get none, as that is
+    /// most of them, and so do their unsigned sessions.
+    fn shield(&self, event: &EventTimelineItem) -> Option<Shield> {
+        let content = event.content();
+        if !self.room.encryption_state().is_encrypted()
+            || event.is_local_echo()
+            || content.as_msglike().is_none()
+            || content.is_redacted()
+            || content.is_unable_to_decrypt()
+        {
+            return None;
+        }
+
+        let Some(info) = event.encryption_info() else {
+            return Some(Shield::Unencrypted);
+        };
+        match &info.verification_state {
+            VerificationState::Verified => Some(Shield::Verified),
+            VerificationState::Unverified(level) => match level {
+                VerificationLevel::UnverifiedIdentity => None,
+                VerificationLevel::UnsignedDevice => self
+                    .verified_senders
+                    .get(event.sender())
+                    .is_some_and(|is_verified| *is_verified)
+                    .then_some(Shield::UnverifiedDevice),
+                VerificationLevel::VerificationViolation => {
+                    Some(Shield::IdentityChanged)
+                }
+                VerificationLevel::None(_)
+                | VerificationLevel::MismatchedSender => {
+                    Some(Shield::UnknownDevice)
+                }
+            },
+        }
+    }
+
+    /// Own messages need the power to redact your own events, other people's
+    /// need the (usually moderator only) power to redact anyone's.
+    fn can_redact(&self, event: &EventTimelineItem) -> bool {
+        if event.content().is_redacted()
+            || event.content().as_msglike().is_none()
+        {
+            return false;
+        }
+        let Some(power_levels) = &self.power_levels else {
+            return false;
+        };
+        let own_user_id = self.room.own_user_id();
+        if event.is_own() {
+            power_levels.user_can_redact_own_event(own_user_id)
+        } else {
+            power_levels.user_can_redact_event_of_other(own_user_id)
+        }
+    }
+
+    /// What we may do to the sender of `event`, given our power level.
+    fn moderation_kinds(
+        &self,
+        event: &EventTimelineItem,
+    ) -> Vec<moderation::Kind> {
+        let Some(power_levels) = &self.power_levels else {
+            return Vec::new();
+        };
+        if event.is_own() || event.content().as_msglike().is_none() {
+            return Vec::new();
+        }
+        [
+            moderation::Kind::Kick,
+            moderation::Kind::Ban,
+            moderation::Kind::PowerLevel,
+        ]
+        .into_iter()
+        .filter(|kind| {
+            kind.is_allowed(
+                power_levels,
+                self.room.own_user_id(),
+                event.sender(),
+            )
+        })
+        .collect()
+    }
+
+    /// Whether `event_id` comes after the newest event we sent a read receipt
+    /// for.
+    fn is_unread(&self, event_id: &OwnedEventId) -> bool {
+        let position = |event_id: &OwnedEventId| {
+            self.items.iter().position(|item| {
+                item.as_event().and_then(|event| event.event_id())
+                    == Some(event_id)
+            })
+        };
+        let Some(shown) = position(event_id) else {
+            return false;
+        };
+        match self.read_up_to.as_ref().and_then(position) {
+            Some(read) => shown > read,
+            None => true,
+        }
+    }
+
+    fn attachment_mut(&mut self, path: &PathBuf) -> Option<&mut Attachment> {
+        self.attachments
+            .iter_mut()
+            .find(|attachment| attachment.path == *path)
+    }
+
+    /// Starts uploading every attachment that isn't being uploaded yet.
+    fn start_uploads(&mut self) -> Vec<Task<Message>> {
+        let mut tasks = Vec::new();
+        for attachment in &mut self.attachments {
+            if attachment.is_uploading() {
+                continue;
+            }
+            let path = attachment.path.clone();
+            let (task, handle) = Task::run(
+                upload::upload(self.timeline.clone(), attachment),
+                move |update| Message::Upload(path.clone(), update),
+            )
+            .abortable();
+            attachment.start(handle);
+            tasks.push(task);
+        }
+        tasks
+    }
+
+    /// Starts downloading the thumbnails of images we haven't seen yet.
+    fn load_thumbnails(&mut self) -> Task<Message> {
+        let mut tasks = Vec::new();
+        for event in self.items.iter().filter_map(|item| item.as_event()) {
+            let Some(MessageType::Image(image)) = event
+                .content()
+                .as_message()
+                .map(|message| message.msgtype())
+            else {
+                continue;
+            };
+            let source = thumbnail_source(image);
+            let uri = media_uri(&source).to_owned();
+            if self.thumbnails.contains_key(&uri) {
+                continue;
+            }
+            self.thumbnails.insert(uri.clone(), Thumbnail::Loading);
+            tasks.push(Task::perform(
+                self.media.clone().thumbnail(self.room.client(), source),
+                move |result| Message::ThumbnailLoaded(uri.clone(), result),
+            ));
+        }
+
+        Task::batch(tasks)
+    }
+
+    /// Finds out whether we verified the senders of messages from sessions
+    /// they didn't sign, which is only worth a warning if we did.
+    fn load_sender_identities(&mut self) -> Task<Message> {
+        let mut tasks = Vec::new();
+        for event in self.items.iter().filter_map(|item| item.as_event()) {
+            let Some(info) = event.encryption_info() else {
+                continue;
+            };
+            if info.verification_state
+                != VerificationState::Unverified(
+                    VerificationLevel::UnsignedDevice,
+                )
+                || self.verified_senders.contains_key(event.sender())
+            {
+                continue;
+            }
+            let user_id = event.sender().to_owned();
+            self.verified_senders.insert(user_id.clone(), false);
+            let client = self.room.client();
+            tasks.push(Task::perform(
+                async move {
+                    let is_verified = client
+                        .encryption()
+                        .get_user_identity(&user_id)
+                        .await
+                        .ok()
+                        .flatten()
+                        .is_some_and(|identity| identity.is_verified());
+                    (user_id, is_verified)
+                },
+                |(user_id, is_verified)| {
+                    Message::SenderIdentityLoaded(user_id, is_verified)
+                },
+            ));
+        }
+
+        Task::batch(tasks)
+    }
+
+    /// Asks the server for replied-to events that aren't in the timeline.
+    fn fetch_missing_replies(&mut self) -> Task<Message> {
+        let mut tasks = Vec::new();
+        for event in self.items.iter().filter_map(|item| item.as_event()) {
+            let Some(in_reply_to) = event
+                .content()
+                .as_msglike()
+                .and_then(|m| m.in_reply_to.as_ref())
+            else {
+                continue;
+            };
+            if in_reply_to.event.is_unavailable()
+                && let Some(event_id) = event.event_id()
+                && self.fetched_details.insert(event_id.to_owned())
+            {
+                tasks.push(Task::perform(
+                    fetch_details(self.timeline.clone(), event_id.to_owned()),
+                    Message::DetailsFetched,
+                ));
+            }
+        }
+
+        Task::batch(tasks)
+    }
+}
+
+const BOLD: Font = Font {
+    weight: font::Weight::Bold,
+    ..Font::DEFAULT
+};
+
+fn action_button(label: &str, message: Message) -> Element<'_, Message> {
+    button(text(label).size(FONT_SIZE - 2))
+        .style(button::secondary)
+        .padding([2, 6])
+        .on_press(message)
+        .into()
+}
+
+/// A small badge for the shield, explaining it and naming the sender's
+/// session on hover.
+fn view_shield(
+    shield: Shield,
+    device_id: Option<&DeviceId>,
+) -> Element<'_, Message> {
+    let (label, style, explanation): (_, fn(&Theme) -> text::Style, _) =
+        match shield {
+            Shield::Verified => (
+                "Verified",
+                text::success,
+                "Sent from a verified session of a verified user.",
+            ),
+            Shield::UnverifiedDevice => (
+                "Unverified session",
+                text::warning,
+                "Sent from a session its owner hasn't verified.",
+            ),
+            Shield::UnknownDevice => (
+                "Unknown session",
+                text::danger,
+                "Sent from a session that couldn't be linked to the sender. \
+                 It may have been deleted, or the keys came from an \
+                 untrusted source.",
+            ),
+            Shield::IdentityChanged => (
+                "Identity changed",
+                text::danger,
+                "The sender's identity changed since you verified them.",
+            ),
+            Shield::Unencrypted => (
+                "Not encrypted",
+                text::danger,
+                "This message was sent without encryption in an encrypted \
+                 room.",
+            ),
+        };
+
+    let mut details = column![text(explanation).size(FONT_SIZE - 2)]
+        .spacing(2)
+        .max_width(DIALOG_WIDTH);
+    if let Some(device_id) = device_id {
+        details = details
+            .push(text(format!("Session {device_id}")).size(FONT_SIZE - 2));
+    }
+
+    tooltip(
+        text(label).size(FONT_SIZE - 3).style(style),
+        container(details).padding(5).style(container::rounded_box),
+        tooltip::Position::Top,
+    )
+    .into()
+}
+
+/// Indents content behind a vertical bar, like a blockquote.
+fn quote<'a>(content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    row![rule::vertical(3), container(content).padding([0, 6])]
+        .height(Length::Shrink)
+        .into()
+}
+
+fn view_redaction(redaction: &Redaction) -> Element<'_, Message> {
+    column![
+        text("Delete message").size(20),
+        text("This can't be undone.").size(FONT_SIZE),
+        text_input("Reason (optional)", &redaction.reason)
+            .on_input(Message::RedactReasonInput)
+            .on_submit(Message::ConfirmRedact)
+            .size(FONT_SIZE),
+        row![
+            button(text("Cancel").size(FONT_SIZE))
+                .style(button::secondary)
+                .on_press(Message::CancelRedact),
+            button(text("Delete").size(FONT_SIZE))
+                .style(button::danger)
+                .on_press(Message::ConfirmRedact)
+        ]
+        .spacing(10)
+    ]
+    .spacing(15)
+    .width(DIALOG_WIDTH)
+    .into()
+}
+
+fn view_in_reply_to(in_reply_to: &InReplyToDetails) -> Element<'_, Message> {
+    let (sender, preview) = match &in_reply_to.event {
+        TimelineDetails::Ready(event) => (
+            sender_name(&event.sender, &event.sender_profile),
+            preview(&event.content),
+        ),
+        TimelineDetails::Error(_) => {
+            (String::new(), String::from("Could not load message"))
+        }
+        _ => (String::new(), String::from("Loading message…")),
+    };
+
+    quote(
+        column![
+            text(sender).size(FONT_SIZE - 2).font(BOLD),
+            text(preview).size(FONT_SIZE - 2)
+        ]
+        .spacing(2),
+    )
+}
+
+fn sender_name(sender: &UserId, profile: &TimelineDetails<Profile>) -> String {
+    match profile {
+        TimelineDetails::Ready(Profile {
+            display_name: Some(display_name),
+            ..
+        }) => display_name.clone(),
+        _ => sender.to_string(),
+    }
+}
+
+/// A single line summary of some content, for reply quotes.
+fn preview(content: &TimelineItemContent) -> String {
+    let body = match content.as_msglike().map(|msglike| &msglike.kind) {
+        Some(MsgLikeKind::Message(message)) => message.body(),
+        Some(MsgLikeKind::Redacted) => "Message deleted",
+        Some(MsgLikeKind::UnableToDecrypt(_)) => "Unable to decrypt message",
+        _ => "Unsupported event",
+    };
+
+    let line = body.lines().next().unwrap_or_default();
+    if line.chars().count() > PREVIEW_LENGTH {
+        let truncated: String = line.chars().take(PREVIEW_LENGTH).collect();
+        format!("{truncated}…")
+    } else {
+        line.to_string()
+    }
+}
+
+/// Why an event couldn't be decrypted, in the user's terms.
+fn utd_explanation(cause: UtdCause) -> &'static str {
+    match cause {
+        UtdCause::SentBeforeWeJoined => {
+            "It was sent before you joined the room."
+        }
+        UtdCause::VerificationViolation => {
+            "The sender's identity changed since you verified them."
+        }
+        UtdCause::UnsignedDevice => {
+            "It was sent from a session its owner hasn't verified."
+        }
+        UtdCause::UnknownDevice => {
+            "It was sent from a session that couldn't be identified."
+        }
+        UtdCause::HistoricalMessageAndBackupIsDisabled => {
+            "It was sent before this session existed, and key backup is off."
+        }
+        UtdCause::HistoricalMessageAndDeviceIsUnverified => {
+            "It was sent before this session existed. Verify this session to \
+             read it."
+        }
+        UtdCause::WithheldForUnverifiedOrInsecureDevice => {
+            "The sender doesn't share keys with unverified sessions. Verify \
+             this session to read it."
+        }
+        UtdCause::WithheldBySender => {
+            "The sender chose not to share the keys for it."
+        }
+        _ => "The keys for it haven't arrived yet.",
+    }
+}
+
+/// Whether the key backup could hold keys we never received.
+fn may_be_in_backup(cause: UtdCause) -> bool {
+    !matches!(
+        cause,
+        UtdCause::SentBeforeWeJoined | UtdCause::WithheldBySender
+    )
+}
+
+/// A file's name and size, with a button to save it.
+fn view_file<'a>(
+    file_name: &'a str,
+    size: Option<UInt>,
+    source: &MediaSource,
+) -> Element<'a, Message> {
+    let mut details = column![text(file_name).size(FONT_SIZE).font(BOLD)];
+    if let Some(size) = size {
+        details = details
+            .push(text(media::format_size(size.into())).size(FONT_SIZE - 2));
+    }
+
+    container(
+        row![
+            details.width(Length::Fill),
+            action_button(
+                "Download",
+                Message::Download(source.clone(), file_name.to_string()),
+            )
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center),
+    )
+    .padding(8)
+    .max_width(media::THUMBNAIL_SIZE)
+    .style(container::rounded_box)
+    .into()
+}
+
+/// A staged attachment: a preview, its caption while waiting and its progress
+/// while uploading.
+fn view_attachment(attachment: &Attachment) -> Element<'_, Message> {
+    let preview: Element<Message> = match &attachment.preview {
+        Some(handle) => image(handle)
+            .width(ATTACHMENT_PREVIEW_SIZE)
+            .height(ATTACHMENT_PREVIEW_SIZE)
+            .content_fit(ContentFit::Cover)
+            .into(),
+        None => container(text("File").size(FONT_SIZE - 2))
+            .center(ATTACHMENT_PREVIEW_SIZE)
+            .style(container::rounded_box)
+            .into(),
+    };
+
+    let mut details = column![
+        text(&attachment.file_name).size(FONT_SIZE).font(BOLD),
+        text(media::format_size(attachment.size)).size(FONT_SIZE - 2)
+    ]
+    .spacing(2)
+    .width(Length::Fill);
+
+    let path = attachment.path.clone();
+    details = match attachment.progress {
+        Some(progress) => {
+            details.push(progress_bar(0.0..=1.0, progress).girth(6))
+        }
+        None => details.push(
+            text_input("Caption (optional)", &attachment.caption)
+                .on_input(move |caption| {
+                    Message::CaptionInput(path.clone(), caption)
+                })
+                .on_submit(Message::Send)
+                .size(FONT_SIZE),
+        ),
+    };
+
+    let remove = if attachment.is_uploading() {
+        "Cancel"
+    } else {
+        "Remove"
+    };
+
+    row![
+        preview,
+        details,
+        action_button(
+            remove,
+            Message::RemoveAttachment(attachment.path.clone())
+        )
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center)
+    .into()
+}
+
+/// Encrypted images can't be thumbnailed by the server, so the sender's own
+/// thumbnail is used when there is one.
+fn thumbnail_source(content: &ImageMessageEventContent) -> MediaSource {
+    content
+        .info
+        .as_ref()
+        .and_then(|info| info.thumbnail_source.clone())
+        .unwrap_or_else(|| content.source.clone())
+}
+
+fn media_uri(source: &MediaSource) -> &OwnedMxcUri {
+    match source {
+        MediaSource::Plain(uri) => uri,
+        MediaSource::Encrypted(file) => &file.url,
+    }
+}
+
+/// Scales an image down, keeping its aspect ratio, to fit in a square of
+/// [`media::THUMBNAIL_SIZE`].
+fn fit_thumbnail(width: f32, height: f32) -> (f32, f32) {
+    let max = media::THUMBNAIL_SIZE as f32;
+    let scale = (max / width.max(height)).min(1.0);
+    (width * scale, height * scale)
+}
+
+/// Renders the `formatted_body` when it is HTML, falling back to the plain
+/// `body` otherwise.
+pub fn view_body(msgtype: &MessageType) -> Element<'_, Message> {
+    let formatted = match msgtype {
+        MessageType::Text(content) => content.formatted.as_ref(),
+        MessageType::Notice(content) => content.formatted.as_ref(),
+        MessageType::Emote(content) => content.formatted.as_ref(),
+        _ => None,
+    };
+
+    let blocks = formatted
+        .filter(|formatted| formatted.format == MessageFormat::Html)
+        .and_then(|formatted| html::parse(&formatted.body));
+
+    match blocks {
+        Some(blocks) => html::view(blocks, Message::LinkClicked),
+        None => text(msgtype.body()).size(FONT_SIZE).into(),
+    }
+}
+
+/// The current items followed by every change made to them.
+fn timeline_updates(
+    timeline: Arc<Timeline>,
+) -> impl Stream<Item = Vec<VectorDiff<Arc<TimelineItem>>>> {
+    stream::once(async move {
+        let (items, updates) = timeline.subscribe().await;
+        stream::once(async { vec![VectorDiff::Reset { values: items }] })
+            .chain(updates)
+    })
+    .flatten()
+}
+
+/// The users typing in a room, every time it changes.
+fn typing_updates(room: Room) -> impl Stream<Item = Vec<OwnedUserId>> {
+    let (guard, receiver) = room.subscribe_to_typing_notifications();
+    stream::unfold((guard, receiver), |(guard, mut receiver)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(user_ids) => return Some((user_ids, (guard, receiver))),
+                Err(_) if receiver.is_closed() => return None,
+                // Missed some updates, the next one is still up to date
+                Err(_) => continue,
+            }
+        }
+    })
+}
+
+async fn send_message(
+    timeline: Arc<Timeline>,
+    body: String,
+    in_reply_to: Option<OwnedEventId>,
+) -> Result<(), String> {
+    let result = match in_reply_to {
+        Some(event_id) => {
+            let content = RoomMessageEventContentWithoutRelation::new(
+                MessageType::text_plain(body),
+            );
+            timeline.send_reply(content, event_id).await
+        }
+        None => timeline
+            .send(RoomMessageEventContent::text_plain(body).into())
+            .await
+            .map(|_handle| ()),
+    };
+    result.map_err(|error| error.to_string())
+}
+
+async fn edit_message(
+    timeline: Arc<Timeline>,
+    item_id: TimelineEventItemId,
+    body: String,
+) -> Result<(), String> {
+    let content = RoomMessageEventContentWithoutRelation::new(
+        MessageType::text_plain(body),
+    );
+    timeline
+        .edit(&item_id, EditedContent::RoomMessage(content))
+        .await
+        .map_err(|error| error.to_string())
+}
+
+async fn redact(
+    timeline: Arc<Timeline>,
+    item_id: TimelineEventItemId,
+    reason: Option<String>,
+) -> Result<(), String> {
+    timeline
+        .redact(&item_id, reason.as_deref())
+        .await
+        .map_err(|error| error.to_string())
+}
+
+async fn toggle_reaction(
+    timeline: Arc<Timeline>,
+    item_id: TimelineEventItemId,
+    key: String,
+) -> Result<(), String> {
+    timeline
+        .toggle_reaction(&item_id, &key)
+        .await
+        .map(|_added| ())
+        .map_err(|error| error.to_string())
+}
+
+async fn typing_notice(room: Room, typing: bool) -> Result<(), String> {
+    room.typing_notice(typing)
+        .await
+        .map_err(|error| error.to_string())
+}
+
+/// Moves our read receipt, and in the main timeline the fully read marker,
+/// to `event_id`. The timeline skips receipts older than ones already sent.
+async fn send_receipts(
+    timeline: Arc<Timeline>,
+    event_id: OwnedEventId,
+) -> Result<(), String> {
+    let result = if timeline.is_threaded() {
+        timeline
+            .send_single_receipt(ReceiptType::Read, event_id)
+            .await
+            .map(|_sent| ())
+    } else {
+        timeline
+            .send_multiple_receipts(
+                Receipts::new()
+                    .fully_read_marker(event_id.clone())
+                    .public_read_receipt(event_id),
+            )
+            .await
+    };
+    result.map_err(|error| error.to_string())
+}
+
+/// Looks for the keys of a Megolm session in the key backup and decrypts
+/// with them. `false` if the backup doesn't have them.
+async fn check_backup(
+    room: Room,
+    timeline: Arc<Timeline>,
+    session_id: String,
+) -> Result<bool, String> {
+    let backups = room.client().encryption().backups();
+    match backups.download_room_key(room.room_id(), &session_id).await {
+        Ok(true) => {
+            timeline.retry_decryption([session_id]).await;
+            Ok(true)
+        }
+        Ok(false) => {
+            Err(String::from("Key backup isn't set up on this session"))
+        }
+        Err(error)
+            if error.client_api_error_kind() == Some(&ErrorKind::NotFound) =>
+        {
+            Ok(false)
+        }
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+async fn power_levels(room: Room) -> Option<RoomPowerLevels> {
+    room.power_levels().await.ok()
+}
+
+async fn fetch_details(
+    timeline: Arc<Timeline>,
+    event_id: OwnedEventId,
+) -> Result<(), String> {
+    timeline
+        .fetch_details_for_event(&event_id)
+        .await
+        .map_err(|error| error.to_string())
+}
+
+async fn paginate_backwards(timeline: Arc<Timeline>) -> Result<bool, String> {
+    timeline
+        .paginate_backwards(PAGINATION_SIZE)
+        .await
+        .map_err(|error| error.to_string())
+}
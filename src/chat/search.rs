@@ -0,0 +1,494 @@
+// A local full-text index of decrypted messages, since the server can't
+// search encrypted rooms. Each account has its own, encrypted on disk with a
+// key kept beside it.
+use crate::APP_NAME;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::event_handler::EventHandlerDropGuard;
+use matrix_sdk::ruma::MilliSecondsSinceUnixEpoch;
+use matrix_sdk::ruma::OwnedEventId;
+use matrix_sdk::ruma::OwnedRoomId;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::RoomId;
+use matrix_sdk::ruma::UserId;
+use matrix_sdk::ruma::events::room::message::OriginalSyncRoomMessageEvent;
+use matrix_sdk::ruma::events::room::message::Relation;
+use matrix_sdk::ruma::events::room::redaction::OriginalSyncRoomRedactionEvent;
+use matrix_sdk_store_encryption::StoreCipher;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
+
+const MAX_HITS: usize = 50;
+// Characters of context kept before the first match in a snippet
+const SNIPPET_LEAD: usize = 30;
+const SNIPPET_LENGTH: usize = 140;
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+const KEY_FILE: &str = "search.key";
+const CIPHER_FILE: &str = "search.cipher";
+const INDEX_FILE: &str = "search.index";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    room_id: OwnedRoomId,
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    timestamp: MilliSecondsSinceUnixEpoch,
+    body: String,
+}
+
+/// A message matching a search, with a snippet of its body split into plain
+/// and highlighted parts.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub room_id: OwnedRoomId,
+    pub event_id: OwnedEventId,
+    pub sender: OwnedUserId,
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+    pub snippet: Vec<(String, bool)>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<OwnedEventId, Entry>,
+    // Lowercased words to the events containing them, sorted for prefix
+    // lookups
+    words: BTreeMap<String, HashSet<OwnedEventId>>,
+    // Saving waits for the saved index to be read, so it isn't overwritten
+    loaded: bool,
+    // Changed since the last save
+    dirty: bool,
+    last_saved: Option<Instant>,
+}
+
+/// The index for one account, shared by the sync handler, the open
+/// timelines and the search box.
+#[derive(Clone)]
+pub struct SearchIndex {
+    dir: PathBuf,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SearchIndex {
+    pub fn new(client: &Client) -> Self {
+        // User IDs contain ':', which some file systems don't allow
+        let account = client
+            .user_id()
+            .map_or("", UserId::as_str)
+            .replace([':', '/', '\\'], "_");
+        Self {
+            dir: dirs::data_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join(APP_NAME)
+                .join(account),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Adds or replaces the text of a message.
+    pub fn insert(
+        &self,
+        room_id: OwnedRoomId,
+        event_id: OwnedEventId,
+        sender: OwnedUserId,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        body: &str,
+    ) {
+        let mut inner = self.lock();
+        if inner
+            .entries
+            .get(&event_id)
+            .is_some_and(|entry| entry.body == body)
+        {
+            return;
+        }
+        inner.remove(&event_id);
+        inner.add(Entry {
+            room_id,
+            event_id,
+            sender,
+            timestamp,
+            body: body.to_string(),
+        });
+        inner.dirty = true;
+    }
+
+    /// Replaces the text of a message with an edit of it. Only the original
+    /// sender can edit a message, and the message keeps its sender and time.
+    pub fn edit(
+        &self,
+        room_id: &RoomId,
+        event_id: &OwnedEventId,
+        sender: &UserId,
+        body: &str,
+    ) {
+        let mut inner = self.lock();
+        let Some(entry) = inner.entries.get(event_id) else {
+            return;
+        };
+        if entry.room_id != room_id
+            || entry.sender != sender
+            || entry.body == body
+        {
+            return;
+        }
+        let entry = Entry {
+            body: body.to_string(),
+            ..entry.clone()
+        };
+        inner.remove(event_id);
+        inner.add(entry);
+        inner.dirty = true;
+    }
+
+    /// Removes a message, if it belongs to `room_id`.
+    pub fn remove(&self, room_id: &RoomId, event_id: &OwnedEventId) {
+        let mut inner = self.lock();
+        if inner
+            .entries
+            .get(event_id)
+            .is_some_and(|entry| entry.room_id == room_id)
+        {
+            inner.remove(event_id);
+            inner.dirty = true;
+        }
+    }
+
+    /// The newest messages containing a word starting with each word of the
+    /// query.
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        let terms: Vec<String> =
+            words(query).map(|(_, word)| word.to_lowercase()).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let inner = self.lock();
+        let mut matches: Option<HashSet<&OwnedEventId>> = None;
+        for term in &terms {
+            let found: HashSet<&OwnedEventId> = inner
+                .words
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.as_str()))
+                .flat_map(|(_, event_ids)| event_ids)
+                .collect();
+            matches = Some(match matches {
+                Some(matches) => &matches & &found,
+                None => found,
+            });
+        }
+
+        let mut entries: Vec<&Entry> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|event_id| inner.entries.get(event_id))
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp));
+        entries
+            .into_iter()
+            .take(MAX_HITS)
+            .map(|entry| Hit {
+                room_id: entry.room_id.clone(),
+                event_id: entry.event_id.clone(),
+                sender: entry.sender.clone(),
+                timestamp: entry.timestamp,
+                snippet: snippet(&entry.body, &terms),
+            })
+            .collect()
+    }
+
+    /// Reads the index saved by an earlier session. Messages indexed since
+    /// then are kept as they are newer. An index that can't be read is thrown
+    /// away, and the next save starts a new one with a new key.
+    pub async fn load(self) -> Result<(), String> {
+        let dir = self.dir.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = read_index(&dir);
+            if result.is_err() {
+                for name in [KEY_FILE, CIPHER_FILE, INDEX_FILE] {
+                    let _ = fs::remove_file(dir.join(name));
+                }
+            }
+            result
+        })
+        .await
+        .map_err(|error| error.to_string())
+        .flatten();
+
+        let mut inner = self.lock();
+        inner.loaded = true;
+        match result {
+            Ok(entries) => {
+                for entry in entries {
+                    if !inner.entries.contains_key(&entry.event_id) {
+                        inner.add(entry);
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => {
+                inner.dirty = true;
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes the index to disk if it changed, at most once per
+    /// [`SAVE_INTERVAL`].
+    pub async fn save(self) -> Result<(), String> {
+        let entries: Vec<Entry> = {
+            let mut inner = self.lock();
+            let recently = inner
+                .last_saved
+                .is_some_and(|saved| saved.elapsed() < SAVE_INTERVAL);
+            if !inner.loaded || !inner.dirty || recently {
+                return Ok(());
+            }
+            inner.dirty = false;
+            inner.last_saved = Some(Instant::now());
+            inner.entries.values().cloned().collect()
+        };
+
+        let dir = self.dir.clone();
+        let result =
+            tokio::task::spawn_blocking(move || write_index(&dir, &entries))
+                .await
+                .map_err(|error| error.to_string())
+                .flatten();
+        // Try again on the next save
+        if result.is_err() {
+            self.lock().dirty = true;
+        }
+        result
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Inner {
+    fn add(&mut self, entry: Entry) {
+        for (_, word) in words(&entry.body) {
+            self.words
+                .entry(word.to_lowercase())
+                .or_default()
+                .insert(entry.event_id.clone());
+        }
+        self.entries.insert(entry.event_id.clone(), entry);
+    }
+
+    fn remove(&mut self, event_id: &OwnedEventId) -> bool {
+        let Some(entry) = self.entries.remove(event_id) else {
+            return false;
+        };
+        for (_, word) in words(&entry.body) {
+            let word = word.to_lowercase();
+            if let Some(event_ids) = self.words.get_mut(&word) {
+                event_ids.remove(event_id);
+                if event_ids.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+        true
+    }
+}
+
+/// The entries of the saved index, or none if there isn't one yet.
+fn read_index(dir: &Path) -> Result<Vec<Entry>, String> {
+    let Ok(key) = fs::read(dir.join(KEY_FILE)) else {
+        return Ok(Vec::new());
+    };
+    let key: [u8; 32] = key
+        .try_into()
+        .map_err(|_| String::from("The search index key is invalid"))?;
+    let cipher =
+        fs::read(dir.join(CIPHER_FILE)).map_err(|error| error.to_string())?;
+    let cipher = StoreCipher::import_with_key(&key, &cipher)
+        .map_err(|error| error.to_string())?;
+    let data =
+        fs::read(dir.join(INDEX_FILE)).map_err(|error| error.to_string())?;
+    cipher
+        .decrypt_value(&data)
+        .map_err(|error| error.to_string())
+}
+
+/// Encrypts `entries` with the index's cipher, creating the key and cipher on
+/// first use.
+fn write_index(dir: &Path, entries: &[Entry]) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+    let key = key(dir)?;
+    let cipher_path = dir.join(CIPHER_FILE);
+    let cipher = match fs::read(&cipher_path) {
+        Ok(cipher) => StoreCipher::import_with_key(&key, &cipher)
+            .map_err(|error| error.to_string())?,
+        Err(_) => {
+            let cipher =
+                StoreCipher::new().map_err(|error| error.to_string())?;
+            let exported = cipher
+                .export_with_key(&key)
+                .map_err(|error| error.to_string())?;
+            fs::write(&cipher_path, exported)
+                .map_err(|error| error.to_string())?;
+            cipher
+        }
+    };
+
+    let data = cipher
+        .encrypt_value(&entries)
+        .map_err(|error| error.to_string())?;
+    // Written next to the index then renamed over it, so a crash never
+    // leaves half an index
+    let temp_path = dir.join("search.index.tmp");
+    fs::write(&temp_path, data).map_err(|error| error.to_string())?;
+    fs::rename(temp_path, dir.join(INDEX_FILE))
+        .map_err(|error| error.to_string())
+}
+
+/// The key of the index, created on first use. It wraps the cipher that
+/// encrypts the messages.
+///
+/// There's no system keyring to keep it in, so the key sits next to the index
+/// in a file only the user can read. That keeps the messages from being read
+/// out of the index files alone, but not by someone who can read all of the
+/// user's files.
+fn key(dir: &Path) -> Result<[u8; 32], String> {
+    let path = dir.join(KEY_FILE);
+    if let Ok(key) = fs::read(&path) {
+        return key
+            .try_into()
+            .map_err(|_| String::from("The search index key is invalid"));
+    }
+
+    let key: [u8; 32] = rand::random();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(&key))
+        .map_err(|error| error.to_string())?;
+    Ok(key)
+}
+
+/// Indexes messages as they arrive through sync, already decrypted. Edits
+/// replace the text of the original message and redactions remove it.
+pub fn index_sync(
+    client: &Client,
+    index: SearchIndex,
+) -> [EventHandlerDropGuard; 2] {
+    let messages = client.add_event_handler({
+        let index = index.clone();
+        move |event: OriginalSyncRoomMessageEvent, room: Room| {
+            let index = index.clone();
+            async move {
+                match &event.content.relates_to {
+                    Some(Relation::Replacement(replacement)) => index.edit(
+                        room.room_id(),
+                        &replacement.event_id,
+                        &event.sender,
+                        replacement.new_content.msgtype.body(),
+                    ),
+                    _ => index.insert(
+                        room.room_id().to_owned(),
+                        event.event_id.clone(),
+                        event.sender.clone(),
+                        event.origin_server_ts,
+                        event.content.body(),
+                    ),
+                }
+            }
+        }
+    });
+    let redactions = client.add_event_handler(
+        move |event: OriginalSyncRoomRedactionEvent, room: Room| {
+            let index = index.clone();
+            async move {
+                if let Some(event_id) =
+                    event.content.redacts.clone().or(event.redacts.clone())
+                {
+                    index.remove(room.room_id(), &event_id);
+                }
+            }
+        },
+    );
+    [
+        client.event_handler_drop_guard(messages),
+        client.event_handler_drop_guard(redactions),
+    ]
+}
+
+/// The words of some text with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| {
+            (word.as_ptr() as usize - text.as_ptr() as usize, word)
+        })
+}
+
+/// A part of `body` around its first match, with every word matching one of
+/// the lowercased `terms` highlighted.
+fn snippet(body: &str, terms: &[String]) -> Vec<(String, bool)> {
+    let matches: Vec<(usize, usize)> = words(body)
+        .filter(|(_, word)| {
+            let word = word.to_lowercase();
+            terms.iter().any(|term| word.starts_with(term.as_str()))
+        })
+        .map(|(start, word)| (start, start + word.len()))
+        .collect();
+
+    // Start a few characters before the first match, on a char boundary
+    let first = matches.first().map_or(0, |(start, _)| *start);
+    let start = body[..first]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_LEAD - 1)
+        .map_or(0, |(index, _)| index);
+    let end = body[start..]
+        .char_indices()
+        .nth(SNIPPET_LENGTH)
+        .map_or(body.len(), |(index, _)| start + index);
+
+    let mut parts = Vec::new();
+    if start > 0 {
+        parts.push((String::from("…"), false));
+    }
+    let mut position = start;
+    for (match_start, match_end) in matches {
+        if match_start >= end {
+            break;
+        }
+        let match_end = match_end.min(end);
+        if match_start > position {
+            parts.push((body[position..match_start].to_string(), false));
+        }
+        parts.push((body[match_start..match_end].to_string(), true));
+        position = match_end;
+    }
+    if position < end {
+        parts.push((body[position..end].to_string(), false));
+    }
+    if end < body.len() {
+        parts.push((String::from("…"), false));
+    }
+    // Snippets stay on one line
+    for (part, _) in &mut parts {
+        *part = part.replace('\n', " ");
+    }
+    parts
+}
@@ -0,0 +1,623 @@
+// Account-wide encryption settings: the server-side key backup, restoring
+// room keys from it, and exporting and importing them as files
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::futures::SinkExt;
+use iced::futures::Stream;
+use iced::futures::StreamExt;
+use iced::futures::future;
+use iced::task;
+use iced::widget::button;
+use iced::widget::column;
+use iced::widget::progress_bar;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::encryption::backups::BackupState;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 480.0;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Close,
+}
+
+/// A room key export or import being set up, waiting for its passphrase.
+enum KeyTransfer {
+    Export {
+        passphrase: String,
+        confirmation: String,
+    },
+    Import {
+        path: PathBuf,
+        passphrase: String,
+    },
+}
+
+/// How far a restore got, in rooms whose keys were downloaded.
+struct Progress {
+    done: usize,
+    total: usize,
+}
+
+pub struct SecuritySettings {
+    client: Client,
+    backup_state: BackupState,
+    // Whether some backup exists on the server, None until we asked
+    exists_on_server: Option<bool>,
+    enabling: bool,
+    recovery_key: String,
+    // Some while restoring
+    restoring: Option<Progress>,
+    restored: bool,
+    key_transfer: Option<KeyTransfer>,
+    transferring: bool,
+    // What the last export or import did
+    transfer_result: Option<String>,
+    error: Option<String>,
+    // Dropping the dialog stops listening to the backup state and cancels a
+    // restore
+    _backup_updates: task::Handle,
+    _restore: Option<task::Handle>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    BackupStateChanged(BackupState),
+    ExistsOnServer(Result<bool, String>),
+    EnableBackup,
+    BackupEnabled(Result<(), String>),
+    RecoveryKeyInput(String),
+    Restore,
+    RestoreProgress(usize, usize),
+    Restored(Result<(), String>),
+    ExportKeys,
+    ImportKeys,
+    ImportFilePicked(Option<PathBuf>),
+    TransferPassphraseInput(String),
+    TransferConfirmationInput(String),
+    StartTransfer,
+    CancelTransfer,
+    Exported(Result<Option<PathBuf>, String>),
+    Imported(Result<(usize, usize), String>),
+    Close,
+}
+
+impl SecuritySettings {
+    pub fn new(client: Client) -> (Self, Task<Message>) {
+        let backups = client.encryption().backups();
+        let (updates, handle) = Task::run(
+            backups
+                .state_stream()
+                .filter_map(|state| future::ready(state.ok())),
+            Message::BackupStateChanged,
+        )
+        .abortable();
+
+        let settings = Self {
+            client,
+            backup_state: backups.state(),
+            exists_on_server: None,
+            enabling: false,
+            recovery_key: String::new(),
+            restoring: None,
+            restored: false,
+            key_transfer: None,
+            transferring: false,
+            transfer_result: None,
+            error: None,
+            _backup_updates: handle.abort_on_drop(),
+            _restore: None,
+        };
+        let task = settings.check_server();
+        (settings, Task::batch([updates, task]))
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::BackupStateChanged(state) => {
+                self.backup_state = state;
+                // The server side changes along with enabling and disabling
+                if state == BackupState::Enabled
+                    || state == BackupState::Unknown
+                {
+                    return Action::Task(self.check_server());
+                }
+            }
+            Message::ExistsOnServer(result) => match result {
+                Ok(exists) => self.exists_on_server = Some(exists),
+                Err(error) => self.error = Some(error),
+            },
+            Message::EnableBackup => {
+                self.enabling = true;
+                self.error = None;
+                let client = self.client.clone();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .encryption()
+                            .recovery()
+                            .enable_backup()
+                            .await
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::BackupEnabled,
+                ));
+            }
+            Message::BackupEnabled(result) => {
+                self.enabling = false;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                }
+            }
+            Message::RecoveryKeyInput(recovery_key) => {
+                self.recovery_key = recovery_key;
+            }
+            Message::Restore => {
+                if self.restoring.is_some() {
+                    return Action::None;
+                }
+                self.restoring = Some(Progress { done: 0, total: 0 });
+                self.restored = false;
+                self.error = None;
+                let (task, handle) = Task::run(
+                    restore(
+                        self.client.clone(),
+                        self.recovery_key.trim().to_string(),
+                    ),
+                    |message| message,
+                )
+                .abortable();
+                self._restore = Some(handle.abort_on_drop());
+                return Action::Task(task);
+            }
+            Message::RestoreProgress(done, total) => {
+                self.restoring = Some(Progress { done, total });
+            }
+            Message::Restored(result) => {
+                self.restoring = None;
+                self._restore = None;
+                match result {
+                    Ok(()) => {
+                        self.recovery_key.clear();
+                        self.restored = true;
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::ExportKeys => {
+                self.key_transfer = Some(KeyTransfer::Export {
+                    passphrase: String::new(),
+                    confirmation: String::new(),
+                });
+                self.transfer_result = None;
+                self.error = None;
+            }
+            Message::ImportKeys => {
+                return Action::Task(Task::perform(
+                    pick_key_file(),
+                    Message::ImportFilePicked,
+                ));
+            }
+            Message::ImportFilePicked(path) => {
+                if let Some(path) = path {
+                    self.key_transfer = Some(KeyTransfer::Import {
+                        path,
+                        passphrase: String::new(),
+                    });
+                    self.transfer_result = None;
+                    self.error = None;
+                }
+            }
+            Message::TransferPassphraseInput(input) => {
+                match &mut self.key_transfer {
+                    Some(KeyTransfer::Export { passphrase, .. })
+                    | Some(KeyTransfer::Import { passphrase, .. }) => {
+                        *passphrase = input;
+                    }
+                    None => (),
+                }
+            }
+            Message::TransferConfirmationInput(input) => {
+                if let Some(KeyTransfer::Export { confirmation, .. }) =
+                    &mut self.key_transfer
+                {
+                    *confirmation = input;
+                }
+            }
+            Message::StartTransfer => {
+                let Some(key_transfer) = &self.key_transfer else {
+                    return Action::None;
+                };
+                if self.transferring {
+                    return Action::None;
+                }
+                let client = self.client.clone();
+                let task = match key_transfer {
+                    KeyTransfer::Export {
+                        passphrase,
+                        confirmation,
+                    } => {
+                        if passphrase.is_empty() {
+                            self.error =
+                                Some(String::from("Enter a passphrase"));
+                            return Action::None;
+                        }
+                        if passphrase != confirmation {
+                            self.error = Some(String::from(
+                                "The passphrases don't match",
+                            ));
+                            return Action::None;
+                        }
+                        Task::perform(
+                            export_keys(client, passphrase.clone()),
+                            Message::Exported,
+                        )
+                    }
+                    KeyTransfer::Import { path, passphrase } => Task::perform(
+                        import_keys(client, path.clone(), passphrase.clone()),
+                        Message::Imported,
+                    ),
+                };
+                self.transferring = true;
+                self.error = None;
+                return Action::Task(task);
+            }
+            Message::CancelTransfer => {
+                if !self.transferring {
+                    self.key_transfer = None;
+                    self.error = None;
+                }
+            }
+            Message::Exported(result) => {
+                self.transferring = false;
+                match result {
+                    Ok(Some(path)) => {
+                        self.key_transfer = None;
+                        self.transfer_result = Some(format!(
+                            "Exported your keys to {}",
+                            path.display()
+                        ));
+                    }
+                    Ok(None) => (),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Imported(result) => {
+                self.transferring = false;
+                match result {
+                    Ok((imported, total)) => {
+                        self.key_transfer = None;
+                        self.transfer_result = Some(format!(
+                            "Imported {imported} new sessions out of {total} \
+                             in the file"
+                        ));
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut content = column![
+            row![
+                text("Security").size(20).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE)).on_press(Message::Close)
+            ]
+            .align_y(Alignment::Center),
+            rule::horizontal(1),
+            text("Key backup").size(FONT_SIZE + 2),
+            text(self.backup_status()).size(FONT_SIZE),
+        ]
+        .spacing(10)
+        .width(WIDTH);
+
+        if self.backup_state == BackupState::Unknown
+            && self.exists_on_server == Some(false)
+        {
+            let mut enable = row![
+                button(text("Enable backup").size(FONT_SIZE)).on_press_maybe(
+                    (!self.enabling).then_some(Message::EnableBackup)
+                )
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center);
+            if self.enabling {
+                enable = enable.push(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                );
+            }
+            content = content.push(enable);
+        }
+
+        if self.exists_on_server == Some(true) {
+            content = content
+                .push(
+                    text(
+                        "Restore your keys from the backup to read old \
+                         encrypted messages on this device.",
+                    )
+                    .size(FONT_SIZE),
+                )
+                .push(
+                    row![
+                        text_input(
+                            "Recovery key or passphrase",
+                            &self.recovery_key
+                        )
+                        .secure(true)
+                        .on_input(Message::RecoveryKeyInput)
+                        .on_submit(Message::Restore)
+                        .size(FONT_SIZE),
+                        button(text("Restore").size(FONT_SIZE)).on_press_maybe(
+                            (self.restoring.is_none()
+                                && !self.recovery_key.trim().is_empty())
+                            .then_some(Message::Restore)
+                        )
+                    ]
+                    .spacing(10)
+                    .align_y(Alignment::Center),
+                );
+        }
+
+        match &self.restoring {
+            Some(Progress { total: 0, .. }) => {
+                content = content
+                    .push(text("Unlocking the backup...").size(FONT_SIZE));
+            }
+            Some(Progress { done, total }) => {
+                content = content
+                    .push(
+                        progress_bar(0.0..=*total as f32, *done as f32)
+                            .girth(8),
+                    )
+                    .push(
+                        text(format!(
+                            "Restored keys for {done} of {total} rooms"
+                        ))
+                        .size(FONT_SIZE - 1),
+                    );
+            }
+            None if self.restored => {
+                content = content.push(
+                    text("Your keys were restored from the backup.")
+                        .size(FONT_SIZE),
+                );
+            }
+            None => (),
+        }
+
+        content = content
+            .push(rule::horizontal(1))
+            .push(text("Export and import keys").size(FONT_SIZE + 2))
+            .push(
+                text(
+                    "Keep your room keys in a passphrase-protected file, or \
+                     import a file exported by another client.",
+                )
+                .size(FONT_SIZE),
+            )
+            .push(self.view_key_transfer());
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        content.into()
+    }
+
+    fn view_key_transfer(&self) -> Element<'_, Message> {
+        let mut content = column![].spacing(10);
+        let (inputs, label, status) = match &self.key_transfer {
+            None => {
+                content = content.push(
+                    row![
+                        button(text("Export keys").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press(Message::ExportKeys),
+                        button(text("Import keys").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press(Message::ImportKeys)
+                    ]
+                    .spacing(10),
+                );
+                if let Some(result) = &self.transfer_result {
+                    content = content.push(text(result).size(FONT_SIZE));
+                }
+                return content.into();
+            }
+            Some(KeyTransfer::Export {
+                passphrase,
+                confirmation,
+            }) => (
+                column![
+                    text_input("Passphrase", passphrase)
+                        .secure(true)
+                        .on_input(Message::TransferPassphraseInput)
+                        .size(FONT_SIZE),
+                    text_input("Repeat the passphrase", confirmation)
+                        .secure(true)
+                        .on_input(Message::TransferConfirmationInput)
+                        .on_submit(Message::StartTransfer)
+                        .size(FONT_SIZE),
+                ],
+                "Export",
+                "Encrypting your keys...",
+            ),
+            Some(KeyTransfer::Import { path, passphrase }) => (
+                column![
+                    text(format!(
+                        "Importing {}",
+                        path.file_name().unwrap_or_default().display()
+                    ))
+                    .size(FONT_SIZE),
+                    text_input("Passphrase of the file", passphrase)
+                        .secure(true)
+                        .on_input(Message::TransferPassphraseInput)
+                        .on_submit(Message::StartTransfer)
+                        .size(FONT_SIZE),
+                ],
+                "Import",
+                "Decrypting and importing keys...",
+            ),
+        };
+
+        let mut buttons = row![text("").width(Length::Fill)]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        if self.transferring {
+            buttons = buttons.push(text(status).size(FONT_SIZE - 1)).push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        buttons = buttons
+            .push(
+                button(text("Cancel").size(FONT_SIZE))
+                    .style(button::secondary)
+                    .on_press_maybe(
+                        (!self.transferring).then_some(Message::CancelTransfer),
+                    ),
+            )
+            .push(button(text(label).size(FONT_SIZE)).on_press_maybe(
+                (!self.transferring).then_some(Message::StartTransfer),
+            ));
+
+        content.push(inputs.spacing(10)).push(buttons).into()
+    }
+
+    fn backup_status(&self) -> &'static str {
+        match self.backup_state {
+            BackupState::Enabled => "Your room keys are being backed up.",
+            BackupState::Creating => "Creating a backup...",
+            BackupState::Enabling | BackupState::Resuming => {
+                "Turning on the backup..."
+            }
+            BackupState::Downloading => "Downloading keys from the backup...",
+            BackupState::Disabling => "Turning off the backup...",
+            BackupState::Unknown => match self.exists_on_server {
+                None => "Checking for a backup...",
+                Some(true) => {
+                    "There is a backup on the server, but this session isn't \
+                     using it yet."
+                }
+                Some(false) => {
+                    "Your room keys aren't backed up. If you lose all your \
+                     sessions, you won't be able to read old encrypted \
+                     messages."
+                }
+            },
+        }
+    }
+
+    fn check_server(&self) -> Task<Message> {
+        let client = self.client.clone();
+        Task::perform(
+            async move {
+                client
+                    .encryption()
+                    .backups()
+                    .fetch_exists_on_server()
+                    .await
+                    .map_err(|error| error.to_string())
+            },
+            Message::ExistsOnServer,
+        )
+    }
+}
+
+/// Unlocks secret storage with the recovery key, which turns the backup on,
+/// then downloads the keys of every encrypted room one room at a time.
+fn restore(
+    client: Client,
+    recovery_key: String,
+) -> impl Stream<Item = Message> {
+    iced::stream::channel(1, async move |mut output| {
+        let result = async {
+            let encryption = client.encryption();
+            encryption
+                .recovery()
+                .recover(&recovery_key)
+                .await
+                .map_err(|error| error.to_string())?;
+
+            let rooms: Vec<_> = client
+                .joined_rooms()
+                .into_iter()
+                .filter(|room| room.encryption_state().is_encrypted())
+                .collect();
+            let total = rooms.len();
+            for (done, room) in rooms.iter().enumerate() {
+                let _ =
+                    output.send(Message::RestoreProgress(done, total)).await;
+                encryption
+                    .backups()
+                    .download_room_keys_for_room(room.room_id())
+                    .await
+                    .map_err(|error| error.to_string())?;
+            }
+            Ok(())
+        }
+        .await;
+
+        let _ = output.send(Message::Restored(result)).await;
+    })
+}
+
+/// Asks where to save the keys, then writes every room key we have there in
+/// the standard Megolm export format. Returns `None` when the user cancels.
+async fn export_keys(
+    client: Client,
+    passphrase: String,
+) -> Result<Option<PathBuf>, String> {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .set_file_name("element-keys.txt")
+        .save_file()
+        .await
+    else {
+        return Ok(None);
+    };
+
+    let path = file.path().to_owned();
+    client
+        .encryption()
+        .export_room_keys(path.clone(), &passphrase, |_session| true)
+        .await
+        .map_err(|error| error.to_string())?;
+    Ok(Some(path))
+}
+
+async fn pick_key_file() -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("Key exports", &["txt"])
+        .pick_file()
+        .await
+        .map(|file| file.path().to_owned())
+}
+
+/// Imports a Megolm key export, returning how many of its sessions were new
+/// and how many it held. Messages waiting for these keys decrypt on their own.
+async fn import_keys(
+    client: Client,
+    path: PathBuf,
+    passphrase: String,
+) -> Result<(usize, usize), String> {
+    client
+        .encryption()
+        .import_room_keys(path, &passphrase)
+        .await
+        .map(|result| (result.imported_count, result.total_count))
+        .map_err(|error| error.to_string())
+}
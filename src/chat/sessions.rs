@@ -0,0 +1,414 @@
+// The account's sessions (devices): renaming them and signing out the ones
+// that are no longer used
+use crate::chat::format_timestamp;
+use crate::chat::password_auth;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Font;
+use iced::Length;
+use iced::Task;
+use iced::font;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::checkbox;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::scrollable;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::ruma::MilliSecondsSinceUnixEpoch;
+use matrix_sdk::ruma::OwnedDeviceId;
+use std::collections::HashSet;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 540.0;
+const LIST_HEIGHT: f32 = 380.0;
+const BOLD: Font = Font {
+    weight: font::Weight::Bold,
+    ..Font::DEFAULT
+};
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    device_id: OwnedDeviceId,
+    display_name: Option<String>,
+    last_seen_ip: Option<String>,
+    last_seen_ts: Option<MilliSecondsSinceUnixEpoch>,
+    verified: bool,
+}
+
+pub struct Sessions {
+    client: Client,
+    // None until the list is loaded
+    sessions: Option<Vec<Session>>,
+    selected: HashSet<OwnedDeviceId>,
+    // The session being renamed and its new name
+    renaming: Option<(OwnedDeviceId, String)>,
+    password: String,
+    running: bool,
+    error: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Loaded(Result<Vec<Session>, String>),
+    Toggle(OwnedDeviceId, bool),
+    Rename(OwnedDeviceId),
+    NameInput(String),
+    SaveName,
+    CancelRename,
+    Renamed(Result<(), String>),
+    PasswordInput(String),
+    SignOut,
+    SignedOut(Result<(), String>),
+    Close,
+}
+
+impl Sessions {
+    pub fn new(client: Client) -> (Self, Task<Message>) {
+        let sessions = Self {
+            client,
+            sessions: None,
+            selected: HashSet::new(),
+            renaming: None,
+            password: String::new(),
+            running: false,
+            error: None,
+        };
+        let task = sessions.load();
+        (sessions, task)
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Loaded(result) => match result {
+                Ok(sessions) => {
+                    self.selected.retain(|device_id| {
+                        sessions
+                            .iter()
+                            .any(|session| session.device_id == *device_id)
+                    });
+                    self.sessions = Some(sessions);
+                }
+                Err(error) => self.error = Some(error),
+            },
+            Message::Toggle(device_id, selected) => {
+                if selected {
+                    self.selected.insert(device_id);
+                } else {
+                    self.selected.remove(&device_id);
+                }
+            }
+            Message::Rename(device_id) => {
+                let name = self
+                    .sessions
+                    .iter()
+                    .flatten()
+                    .find(|session| session.device_id == device_id)
+                    .and_then(|session| session.display_name.clone())
+                    .unwrap_or_default();
+                self.renaming = Some((device_id, name));
+            }
+            Message::NameInput(name) => {
+                if let Some((_device_id, new_name)) = &mut self.renaming {
+                    *new_name = name;
+                }
+            }
+            Message::SaveName => {
+                let Some((device_id, name)) = self.renaming.clone() else {
+                    return Action::None;
+                };
+                self.running = true;
+                self.error = None;
+                let client = self.client.clone();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .rename_device(&device_id, name.trim())
+                            .await
+                            .map(|_response| ())
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::Renamed,
+                ));
+            }
+            Message::CancelRename => self.renaming = None,
+            Message::Renamed(result) => {
+                self.running = false;
+                match result {
+                    Ok(()) => {
+                        self.renaming = None;
+                        return Action::Task(self.load());
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::PasswordInput(password) => self.password = password,
+            Message::SignOut => {
+                if self.selected.is_empty() {
+                    return Action::None;
+                }
+                self.running = true;
+                self.error = None;
+                return Action::Task(Task::perform(
+                    sign_out(
+                        self.client.clone(),
+                        self.selected.iter().cloned().collect(),
+                        self.password.clone(),
+                    ),
+                    Message::SignedOut,
+                ));
+            }
+            Message::SignedOut(result) => {
+                self.running = false;
+                match result {
+                    Ok(()) => {
+                        self.password.clear();
+                        self.selected.clear();
+                        return Action::Task(self.load());
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Close => return Action::Close,
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut list = Column::new().spacing(8);
+        match &self.sessions {
+            None if self.error.is_none() => {
+                list = list.push(center_x(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                ));
+            }
+            None => (),
+            Some(sessions) => {
+                for session in sessions {
+                    list = list.push(self.view_session(session));
+                }
+            }
+        }
+
+        let mut content = column![
+            row![
+                text("Sessions").size(20).width(Length::Fill),
+                button(text("Close").size(FONT_SIZE)).on_press(Message::Close)
+            ]
+            .align_y(Alignment::Center),
+            rule::horizontal(1),
+            scrollable(list).height(LIST_HEIGHT),
+        ]
+        .spacing(10)
+        .width(WIDTH);
+
+        if !self.selected.is_empty() {
+            let count = match self.selected.len() {
+                1 => String::from("1 session"),
+                count => format!("{count} sessions"),
+            };
+            let mut sign_out = row![
+                text_input("Account password", &self.password)
+                    .secure(true)
+                    .on_input(Message::PasswordInput)
+                    .on_submit(Message::SignOut)
+                    .size(FONT_SIZE),
+                button(text(format!("Sign out {count}")).size(FONT_SIZE))
+                    .style(button::danger)
+                    .on_press_maybe(
+                        (!self.running).then_some(Message::SignOut)
+                    )
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center);
+            if self.running {
+                sign_out = sign_out.push(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                );
+            }
+            content = content.push(rule::horizontal(1)).push(sign_out);
+        }
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        content.into()
+    }
+
+    fn view_session<'a>(
+        &'a self,
+        session: &'a Session,
+    ) -> Element<'a, Message> {
+        let is_current = self.client.device_id() == Some(&*session.device_id);
+
+        let name: Element<Message> = match &self.renaming {
+            Some((device_id, name)) if *device_id == session.device_id => row![
+                text_input("Session name", name)
+                    .on_input(Message::NameInput)
+                    .on_submit(Message::SaveName)
+                    .size(FONT_SIZE),
+                button(text("Save").size(FONT_SIZE - 1)).on_press_maybe(
+                    (!self.running).then_some(Message::SaveName)
+                ),
+                button(text("Cancel").size(FONT_SIZE - 1))
+                    .style(button::secondary)
+                    .on_press(Message::CancelRename)
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+            .into(),
+            _ => {
+                let mut name = row![
+                    text(
+                        session
+                            .display_name
+                            .as_deref()
+                            .unwrap_or(session.device_id.as_str())
+                    )
+                    .size(FONT_SIZE)
+                    .font(BOLD)
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center);
+                if is_current {
+                    name = name.push(
+                        container(text("This session").size(FONT_SIZE - 3))
+                            .padding([1, 5])
+                            .style(container::primary),
+                    );
+                }
+                name.push(
+                    button(text("Rename").size(FONT_SIZE - 3))
+                        .padding([2, 4])
+                        .style(button::text)
+                        .on_press(Message::Rename(session.device_id.clone())),
+                )
+                .into()
+            }
+        };
+
+        let mut details = vec![session.device_id.to_string()];
+        if let Some(ip) = &session.last_seen_ip {
+            details.push(ip.clone());
+        }
+        if let Some(timestamp) = session.last_seen_ts {
+            details.push(format!("last seen {}", format_timestamp(timestamp)));
+        }
+
+        let mut line = row![].spacing(10).align_y(Alignment::Center);
+        if !is_current {
+            line = line.push(
+                checkbox(self.selected.contains(&session.device_id))
+                    .on_toggle(|selected| {
+                        Message::Toggle(session.device_id.clone(), selected)
+                    })
+                    .size(14),
+            );
+        }
+        line.push(
+            column![
+                name,
+                text(details.join(" · ")).size(FONT_SIZE - 2),
+                text(if session.verified {
+                    "Verified"
+                } else {
+                    "Unverified"
+                })
+                .size(FONT_SIZE - 2)
+                .style(if session.verified {
+                    text::success
+                } else {
+                    text::warning
+                })
+            ]
+            .spacing(2)
+            .width(Length::Fill),
+        )
+        .into()
+    }
+
+    fn load(&self) -> Task<Message> {
+        Task::perform(load_sessions(self.client.clone()), Message::Loaded)
+    }
+}
+
+/// The account's devices with their verification status, this one first and
+/// the rest by when they were last seen.
+async fn load_sessions(client: Client) -> Result<Vec<Session>, String> {
+    let response = client.devices().await.map_err(|error| error.to_string())?;
+    let user_id = client
+        .user_id()
+        .ok_or_else(|| String::from("Not logged in"))?
+        .to_owned();
+
+    let mut sessions = Vec::new();
+    for device in response.devices {
+        let verified = client
+            .encryption()
+            .get_device(&user_id, &device.device_id)
+            .await
+            .ok()
+            .flatten()
+            .is_some_and(|device| device.is_verified());
+        sessions.push(Session {
+            device_id: device.device_id,
+            display_name: device.display_name,
+            last_seen_ip: device.last_seen_ip,
+            last_seen_ts: device.last_seen_ts,
+            verified,
+        });
+    }
+
+    let current = client.device_id().map(ToOwned::to_owned);
+    sessions.sort_by_key(|session| {
+        (
+            Some(&session.device_id) != current.as_ref(),
+            std::cmp::Reverse(session.last_seen_ts),
+        )
+    });
+    Ok(sessions)
+}
+
+/// Deletes the devices, confirming with the password when the server asks.
+async fn sign_out(
+    client: Client,
+    devices: Vec<OwnedDeviceId>,
+    password: String,
+) -> Result<(), String> {
+    let Err(error) = client.delete_devices(&devices, None).await else {
+        return Ok(());
+    };
+    let (Some(info), Some(user_id)) =
+        (error.as_uiaa_response(), client.user_id())
+    else {
+        return Err(error.to_string());
+    };
+    if password.is_empty() {
+        return Err(String::from("Enter your password to sign out sessions"));
+    }
+
+    let auth = password_auth(user_id, info.session.clone(), password);
+    client
+        .delete_devices(&devices, Some(auth))
+        .await
+        .map(|_response| ())
+        .map_err(|error| match error.as_uiaa_response() {
+            Some(_) => String::from("Wrong password"),
+            None => error.to_string(),
+        })
+}
@@ -0,0 +1,346 @@
+// The rooms and subspaces of a space, shown as a tree in place of the room
+// list while the space is selected
+use crate::chat::room_name;
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::task;
+use iced::widget::Column;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::container;
+use iced::widget::row;
+use iced::widget::text;
+use matrix_sdk::Client;
+use matrix_sdk::Room;
+use matrix_sdk::RoomState;
+use matrix_sdk::ruma::OwnedRoomId;
+use matrix_sdk::ruma::OwnedRoomOrAliasId;
+use matrix_sdk::ruma::OwnedServerName;
+use matrix_sdk::ruma::RoomId;
+use matrix_sdk::ruma::api::client::space::get_hierarchy;
+use matrix_sdk::ruma::room::RoomType;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const INDENT: f32 = 14.0;
+// Stops at this many pages so huge spaces don't keep loading forever
+const MAX_PAGES: usize = 10;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Open(OwnedRoomId),
+    Joined(Room),
+}
+
+#[derive(Debug, Clone)]
+pub struct Child {
+    room_id: OwnedRoomId,
+    via: Vec<OwnedServerName>,
+}
+
+/// A room or subspace as described by the space hierarchy.
+#[derive(Debug, Clone)]
+pub struct SpaceRoom {
+    name: String,
+    num_joined_members: u64,
+    is_space: bool,
+    children: Vec<Child>,
+}
+
+pub struct SpaceTree {
+    client: Client,
+    space: Room,
+    // Every room found in the hierarchy, by ID. None until the first page
+    // is loaded.
+    rooms: Option<HashMap<OwnedRoomId, SpaceRoom>>,
+    // Subspaces whose children are shown
+    expanded: HashSet<OwnedRoomId>,
+    joining: HashSet<OwnedRoomId>,
+    error: Option<String>,
+    // Dropping the tree stops loading the hierarchy
+    _loading: task::Handle,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Loaded(Result<Vec<(OwnedRoomId, SpaceRoom)>, String>),
+    Toggle(OwnedRoomId),
+    Open(OwnedRoomId),
+    Join(OwnedRoomId, Vec<OwnedServerName>),
+    Joined(OwnedRoomId, Result<Room, String>),
+}
+
+impl SpaceTree {
+    pub fn new(client: Client, space: Room) -> (Self, Task<Message>) {
+        let (task, handle) = Task::perform(
+            load_hierarchy(client.clone(), space.room_id().to_owned()),
+            Message::Loaded,
+        )
+        .abortable();
+
+        (
+            Self {
+                client,
+                space,
+                rooms: None,
+                expanded: HashSet::new(),
+                joining: HashSet::new(),
+                error: None,
+                _loading: handle.abort_on_drop(),
+            },
+            task,
+        )
+    }
+
+    pub fn space(&self) -> &Room {
+        &self.space
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Loaded(result) => match result {
+                Ok(rooms) => self.rooms = Some(rooms.into_iter().collect()),
+                Err(error) => self.error = Some(error),
+            },
+            Message::Toggle(room_id) => {
+                if !self.expanded.remove(&room_id) {
+                    self.expanded.insert(room_id);
+                }
+            }
+            Message::Open(room_id) => return Action::Open(room_id),
+            Message::Join(room_id, via) => {
+                if !self.joining.insert(room_id.clone()) {
+                    return Action::None;
+                }
+                self.error = None;
+                let client = self.client.clone();
+                let id: OwnedRoomOrAliasId = room_id.clone().into();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .join_room_by_id_or_alias(&id, &via)
+                            .await
+                            .map_err(|error| error.to_string())
+                    },
+                    move |result| Message::Joined(room_id.clone(), result),
+                ));
+            }
+            Message::Joined(room_id, result) => {
+                self.joining.remove(&room_id);
+                match result {
+                    Ok(room) => return Action::Joined(room),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+        }
+
+        Action::None
+    }
+
+    /// The space's children, with subspaces expandable in place.
+    pub fn view(&self, selected: Option<&RoomId>) -> Element<'_, Message> {
+        let mut items: Vec<Element<Message>> = Vec::new();
+
+        match &self.rooms {
+            None if self.error.is_none() => items.push(
+                center_x(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                )
+                .into(),
+            ),
+            None => (),
+            Some(rooms) => {
+                let mut visited = HashSet::from([self.space.room_id()]);
+                if let Some(space) = rooms.get(self.space.room_id()) {
+                    self.view_children(
+                        rooms,
+                        space,
+                        0,
+                        selected,
+                        &mut visited,
+                        &mut items,
+                    );
+                }
+                if items.is_empty() {
+                    items.push(
+                        text("This space has no rooms yet")
+                            .size(FONT_SIZE)
+                            .into(),
+                    );
+                }
+            }
+        }
+        if let Some(error) = &self.error {
+            items.push(text(error).size(FONT_SIZE).into());
+        }
+
+        Column::with_children(items).spacing(2).into()
+    }
+
+    fn view_children<'a>(
+        &'a self,
+        rooms: &'a HashMap<OwnedRoomId, SpaceRoom>,
+        space: &'a SpaceRoom,
+        depth: usize,
+        selected: Option<&RoomId>,
+        // Spaces can contain each other, so each one is only shown once
+        visited: &mut HashSet<&'a RoomId>,
+        items: &mut Vec<Element<'a, Message>>,
+    ) {
+        for child in &space.children {
+            let Some(room) = rooms.get(&child.room_id) else {
+                continue;
+            };
+            if room.is_space && !visited.insert(&child.room_id) {
+                continue;
+            }
+
+            let joined = self
+                .client
+                .get_room(&child.room_id)
+                .filter(|room| room.state() == RoomState::Joined);
+            let name = match &joined {
+                Some(room) => room_name(room),
+                None => room.name.clone(),
+            };
+            let expanded = self.expanded.contains(&child.room_id);
+
+            let entry: Element<Message> = if room.is_space {
+                button(
+                    text(format!(
+                        "{} {name}",
+                        if expanded { "▾" } else { "▸" }
+                    ))
+                    .size(FONT_SIZE),
+                )
+                .width(Length::Fill)
+                .style(button::text)
+                .on_press(Message::Toggle(child.room_id.clone()))
+                .into()
+            } else if joined.is_some() {
+                button(text(name).size(FONT_SIZE))
+                    .width(Length::Fill)
+                    .style(if selected == Some(&*child.room_id) {
+                        button::primary
+                    } else {
+                        button::text
+                    })
+                    .on_press(Message::Open(child.room_id.clone()))
+                    .into()
+            } else {
+                text(format!("{name} · {}", room.num_joined_members))
+                    .size(FONT_SIZE)
+                    .width(Length::Fill)
+                    .into()
+            };
+
+            let mut line = row![entry].spacing(5).align_y(Alignment::Center);
+            if joined.is_none() {
+                let joining = self.joining.contains(&child.room_id);
+                line = line.push(
+                    button(text("Join").size(FONT_SIZE - 2))
+                        .style(button::secondary)
+                        .on_press_maybe((!joining).then(|| {
+                            Message::Join(
+                                child.room_id.clone(),
+                                child.via.clone(),
+                            )
+                        })),
+                );
+            }
+            items.push(
+                container(line)
+                    .padding(iced::Padding::ZERO.left(depth as f32 * INDENT))
+                    .into(),
+            );
+
+            if room.is_space && expanded {
+                self.view_children(
+                    rooms,
+                    room,
+                    depth + 1,
+                    selected,
+                    visited,
+                    items,
+                );
+            }
+        }
+    }
+}
+
+/// Every page of the space's hierarchy, up to [`MAX_PAGES`].
+async fn load_hierarchy(
+    client: Client,
+    space_id: OwnedRoomId,
+) -> Result<Vec<(OwnedRoomId, SpaceRoom)>, String> {
+    let mut rooms = Vec::new();
+    let mut from = None;
+
+    for _page in 0..MAX_PAGES {
+        let mut request = get_hierarchy::v1::Request::new(space_id.clone());
+        request.from = from;
+        let response = client
+            .send(request)
+            .await
+            .map_err(|error| error.to_string())?;
+
+        for chunk in response.rooms {
+            let summary = chunk.summary;
+            let mut children: Vec<(Option<String>, Child)> = chunk
+                .children_state
+                .iter()
+                .filter_map(|event| event.deserialize().ok())
+                .map(|event| {
+                    (
+                        event.content.order.map(|order| order.to_string()),
+                        Child {
+                            room_id: event.state_key,
+                            via: event.content.via,
+                        },
+                    )
+                })
+                .collect();
+            // Ordered children first, the rest by ID as the spec suggests
+            children.sort_by(|(a_order, a), (b_order, b)| {
+                match (a_order, b_order) {
+                    (Some(a_order), Some(b_order)) => a_order.cmp(b_order),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+                .then_with(|| a.room_id.cmp(&b.room_id))
+            });
+
+            let name = summary
+                .name
+                .clone()
+                .or(summary.canonical_alias.as_ref().map(ToString::to_string))
+                .unwrap_or_else(|| summary.room_id.to_string());
+            rooms.push((
+                summary.room_id,
+                SpaceRoom {
+                    name,
+                    num_joined_members: summary.num_joined_members.into(),
+                    is_space: summary.room_type == Some(RoomType::Space),
+                    children: children
+                        .into_iter()
+                        .map(|(_order, child)| child)
+                        .collect(),
+                },
+            ));
+        }
+
+        from = response.next_batch;
+        if from.is_none() {
+            break;
+        }
+    }
+
+    Ok(rooms)
+}
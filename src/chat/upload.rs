@@ -0,0 +1,232 @@
+// Sends files dropped onto a room, reporting how far along each upload is
+use iced::futures::SinkExt;
+use iced::futures::Stream;
+use iced::futures::channel::mpsc;
+use iced::futures::future;
+use iced::futures::future::Either;
+use iced::task;
+use iced::widget::image::Handle;
+use image::DynamicImage;
+use image::ImageFormat;
+use matrix_sdk::attachment::AttachmentInfo;
+use matrix_sdk::attachment::BaseAudioInfo;
+use matrix_sdk::attachment::BaseFileInfo;
+use matrix_sdk::attachment::BaseImageInfo;
+use matrix_sdk::attachment::BaseVideoInfo;
+use matrix_sdk::attachment::Thumbnail;
+use matrix_sdk::ruma::UInt;
+use matrix_sdk::ruma::events::room::message::TextMessageEventContent;
+use matrix_sdk_ui::Timeline;
+use matrix_sdk_ui::timeline::AttachmentConfig;
+use matrix_sdk_ui::timeline::AttachmentSource;
+use mime::Mime;
+use std::fs;
+use std::future::IntoFuture;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::Arc;
+
+// Images bigger than this get a thumbnail so clients don't have to download
+// the whole file for a preview
+const THUMBNAIL_SIZE: u32 = 800;
+
+/// A file waiting to be sent, or being sent.
+pub struct Attachment {
+    pub path: PathBuf,
+    pub file_name: String,
+    pub size: u64,
+    pub mime: Mime,
+    pub preview: Option<Handle>,
+    pub caption: String,
+    // Fraction uploaded while the upload is running
+    pub progress: Option<f32>,
+    // Dropping the attachment cancels its upload
+    upload: Option<task::Handle>,
+}
+
+impl Attachment {
+    pub fn new(path: PathBuf) -> Result<Self, String> {
+        let metadata =
+            fs::metadata(&path).map_err(|error| error.to_string())?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", path.display()));
+        }
+
+        let mime = mime_guess::from_path(&path).first_or_octet_stream();
+        let preview =
+            (mime.type_() == mime::IMAGE).then(|| Handle::from_path(&path));
+
+        Ok(Self {
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            size: metadata.len(),
+            mime,
+            preview,
+            caption: String::new(),
+            progress: None,
+            upload: None,
+            path,
+        })
+    }
+
+    pub fn is_uploading(&self) -> bool {
+        self.upload.is_some()
+    }
+
+    pub fn start(&mut self, upload: task::Handle) {
+        self.progress = Some(0.0);
+        self.upload = Some(upload.abort_on_drop());
+    }
+
+    /// Forgets about a failed upload so it can be retried.
+    pub fn reset(&mut self) {
+        self.progress = None;
+        self.upload = None;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Update {
+    Progress(f32),
+    Finished(Result<(), String>),
+}
+
+/// Sends an attachment as `m.image`, `m.video`, `m.audio` or `m.file`
+/// depending on its mimetype, reporting progress as it goes.
+pub fn upload(
+    timeline: Arc<Timeline>,
+    attachment: &Attachment,
+) -> impl Stream<Item = Update> + use<> {
+    let path = attachment.path.clone();
+    let file_name = attachment.file_name.clone();
+    let mime = attachment.mime.clone();
+    let caption = Some(attachment.caption.trim().to_string())
+        .filter(|caption| !caption.is_empty());
+
+    iced::stream::channel(16, async move |mut output| {
+        let result =
+            send(timeline, path, file_name, mime, caption, output.clone())
+                .await;
+        let _ = output.send(Update::Finished(result)).await;
+    })
+}
+
+async fn send(
+    timeline: Arc<Timeline>,
+    path: PathBuf,
+    file_name: String,
+    mime: Mime,
+    caption: Option<String>,
+    mut output: mpsc::Sender<Update>,
+) -> Result<(), String> {
+    // Reading and decoding a big image takes long enough to stall the
+    // runtime, so it happens on a thread of its own
+    let (data, config) = tokio::task::spawn_blocking({
+        let mime = mime.clone();
+        move || {
+            let data = fs::read(&path).map_err(|error| error.to_string())?;
+            let config = attachment_config(&data, &mime, caption);
+            Ok::<_, String>((data, config))
+        }
+    })
+    .await
+    .map_err(|error| error.to_string())??;
+
+    let send = timeline.send_attachment(
+        AttachmentSource::Data {
+            bytes: data,
+            filename: file_name,
+        },
+        mime,
+        config,
+    );
+    let mut progress = send.subscribe_to_send_progress();
+
+    let upload = pin!(send.into_future());
+    let report = pin!(async move {
+        while let Some(progress) = progress.next().await {
+            if progress.total > 0 {
+                let fraction = progress.current as f32 / progress.total as f32;
+                let _ = output.send(Update::Progress(fraction)).await;
+            }
+        }
+    });
+
+    let result = match future::select(upload, report).await {
+        Either::Left((result, _report)) => result,
+        Either::Right(((), upload)) => upload.await,
+    };
+    result.map_err(|error| error.to_string())
+}
+
+/// Size and, for images, dimensions and a thumbnail.
+fn attachment_config(
+    data: &[u8],
+    mime: &Mime,
+    caption: Option<String>,
+) -> AttachmentConfig {
+    let size = UInt::new(data.len() as u64);
+
+    let (info, thumbnail) = match mime.type_() {
+        mime::IMAGE => match image::load_from_memory(data) {
+            Ok(image) => (
+                AttachmentInfo::Image(BaseImageInfo {
+                    width: Some(UInt::from(image.width())),
+                    height: Some(UInt::from(image.height())),
+                    size,
+                    ..Default::default()
+                }),
+                thumbnail(&image),
+            ),
+            Err(_error) => (AttachmentInfo::File(BaseFileInfo { size }), None),
+        },
+        mime::VIDEO => (
+            AttachmentInfo::Video(BaseVideoInfo {
+                size,
+                ..Default::default()
+            }),
+            None,
+        ),
+        mime::AUDIO => (
+            AttachmentInfo::Audio(BaseAudioInfo {
+                size,
+                ..Default::default()
+            }),
+            None,
+        ),
+        _ => (AttachmentInfo::File(BaseFileInfo { size }), None),
+    };
+
+    AttachmentConfig {
+        info: Some(info),
+        thumbnail,
+        caption: caption.map(TextMessageEventContent::plain),
+        ..Default::default()
+    }
+}
+
+/// A JPEG no bigger than [`THUMBNAIL_SIZE`], for images bigger than that.
+fn thumbnail(image: &DynamicImage) -> Option<Thumbnail> {
+    if image.width() <= THUMBNAIL_SIZE && image.height() <= THUMBNAIL_SIZE {
+        return None;
+    }
+
+    let thumbnail = DynamicImage::from(
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).into_rgb8(),
+    );
+    let mut data = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+        .ok()?;
+
+    Some(Thumbnail {
+        width: UInt::from(thumbnail.width()),
+        height: UInt::from(thumbnail.height()),
+        size: UInt::new(data.len() as u64)?,
+        content_type: mime::IMAGE_JPEG,
+        data,
+    })
+}
@@ -0,0 +1,790 @@
+// Interactive verification of another user or of one of our own sessions by
+// comparing emoji (SAS) or scanning a QR code
+use crate::loading_spinner::Spinner;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::futures::SinkExt;
+use iced::futures::Stream;
+use iced::futures::future;
+use iced::task;
+use iced::widget::Image;
+use iced::widget::Row;
+use iced::widget::button;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::image::FilterMethod;
+use iced::widget::image::Handle;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use matrix_sdk::Client;
+use matrix_sdk::encryption::verification::CancelInfo;
+use matrix_sdk::encryption::verification::Emoji;
+use matrix_sdk::encryption::verification::QrVerification;
+use matrix_sdk::encryption::verification::QrVerificationData;
+use matrix_sdk::encryption::verification::QrVerificationState;
+use matrix_sdk::encryption::verification::SasState;
+use matrix_sdk::encryption::verification::SasVerification;
+use matrix_sdk::encryption::verification::VerificationRequest;
+use matrix_sdk::encryption::verification::VerificationRequestState;
+use matrix_sdk::ruma::OwnedUserId;
+use matrix_sdk::ruma::events::key::verification::VerificationMethod;
+use matrix_sdk::ruma::events::key::verification::cancel::CancelCode;
+use matrix_sdk::ruma::events::key::verification::request::ToDeviceKeyVerificationRequestEvent;
+use matrix_sdk::ruma::events::room::message::MessageType;
+use matrix_sdk::ruma::events::room::message::OriginalSyncRoomMessageEvent;
+use matrix_sdk::sleep::sleep;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 480.0;
+const EMOJI_SIZE: u32 = 32;
+const QR_SIZE: f32 = 240.0;
+// White border the QR code spec asks for around the code, in modules
+const QR_QUIET_ZONE: usize = 4;
+// Requests and SAS flows expire after ten minutes according to the spec
+const TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    // Carries the cancellation of a verification that was still going on
+    Close(Task<Message>),
+}
+
+enum Stage {
+    // Waiting for the other side to accept our request
+    Requesting,
+    // Their request, which we haven't answered yet
+    Incoming,
+    // Accepted, waiting for the keys to be exchanged
+    Starting,
+    // Ready, the user picks between the QR code and emoji
+    Choose {
+        qr_code: Option<Handle>,
+        can_scan: bool,
+    },
+    // The other side scanned our QR code, the user checks they say so
+    Scanned,
+    Compare {
+        emojis: Option<Box<[Emoji; 7]>>,
+        decimals: (u16, u16, u16),
+    },
+    // We confirmed, waiting for the other side to do the same
+    Confirmed,
+    Done,
+    Cancelled(String),
+}
+
+pub struct Verification {
+    user_id: OwnedUserId,
+    name: String,
+    is_self_verification: bool,
+    stage: Stage,
+    request: Option<VerificationRequest>,
+    sas: Option<SasVerification>,
+    qr: Option<QrVerification>,
+    // Whether the other side can show a QR code for us to scan
+    can_scan: bool,
+    running: bool,
+    error: Option<String>,
+    // Dropping the dialog stops listening to the request and SAS or QR flow
+    _request_updates: Option<task::Handle>,
+    _sas_updates: Option<task::Handle>,
+    _qr_updates: Option<task::Handle>,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Requested(Result<VerificationRequest, String>),
+    RequestChanged(Box<VerificationRequestState>),
+    SasStarted(Result<Option<SasVerification>, String>),
+    SasChanged(Box<SasState>),
+    QrGenerated(Result<Option<QrVerification>, String>),
+    QrChanged(Box<QrVerificationState>),
+    CompareEmoji,
+    ScanQrCode,
+    QrCodeRead(Result<Option<Box<QrVerificationData>>, String>),
+    ConfirmScanned,
+    Accept,
+    Confirm,
+    Mismatch,
+    Cancel,
+    Finished(Result<(), String>),
+    TimedOut,
+    // Clicking outside the dialog, which only closes it once it's over
+    Dismiss,
+    Close,
+}
+
+impl Verification {
+    /// Asks `user_id` to verify. Our own user ID verifies this session
+    /// against our other sessions.
+    pub fn outgoing(
+        client: Client,
+        user_id: OwnedUserId,
+        name: String,
+    ) -> (Self, Task<Message>) {
+        let is_self_verification = client.user_id() == Some(&*user_id);
+        let task = Task::perform(
+            request_verification(client, user_id.clone()),
+            Message::Requested,
+        );
+
+        (
+            Self {
+                user_id,
+                name,
+                is_self_verification,
+                stage: Stage::Requesting,
+                request: None,
+                sas: None,
+                qr: None,
+                can_scan: false,
+                running: true,
+                error: None,
+                _request_updates: None,
+                _sas_updates: None,
+                _qr_updates: None,
+            },
+            Task::batch([task, timeout()]),
+        )
+    }
+
+    /// Shows a request someone else sent us.
+    pub fn incoming(request: VerificationRequest) -> (Self, Task<Message>) {
+        let mut verification = Self {
+            user_id: request.other_user_id().to_owned(),
+            name: request.other_user_id().to_string(),
+            is_self_verification: request.is_self_verification(),
+            stage: Stage::Incoming,
+            request: None,
+            sas: None,
+            qr: None,
+            can_scan: false,
+            running: false,
+            error: None,
+            _request_updates: None,
+            _sas_updates: None,
+            _qr_updates: None,
+        };
+        let task = verification.watch_request(request);
+        (verification, Task::batch([task, timeout()]))
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Requested(result) => {
+                self.running = false;
+                match result {
+                    Ok(request) => {
+                        return Action::Task(self.watch_request(request));
+                    }
+                    Err(error) => self.stage = Stage::Cancelled(error),
+                }
+            }
+            Message::RequestChanged(state) => match *state {
+                // Show our QR code if they can scan it, the user may still
+                // switch to emoji
+                VerificationRequestState::Ready { their_methods, .. } => {
+                    let Some(request) = self.request.clone() else {
+                        return Action::None;
+                    };
+                    if self.sas.is_some() || self.qr.is_some() {
+                        return Action::None;
+                    }
+                    self.can_scan = their_methods
+                        .contains(&VerificationMethod::QrCodeShowV1);
+                    self.stage = Stage::Starting;
+                    return Action::Task(Task::perform(
+                        async move {
+                            request
+                                .generate_qr_code()
+                                .await
+                                .map_err(|error| error.to_string())
+                        },
+                        Message::QrGenerated,
+                    ));
+                }
+                VerificationRequestState::Transitioned {
+                    verification, ..
+                } => {
+                    if let Some(sas) = verification.clone().sas() {
+                        return Action::Task(self.watch_sas(sas));
+                    }
+                    if let Some(qr) = verification.qr() {
+                        return Action::Task(self.watch_qr(qr));
+                    }
+                }
+                VerificationRequestState::Done => self.stage = Stage::Done,
+                VerificationRequestState::Cancelled(info) => {
+                    self.cancelled(&info);
+                }
+                _ => (),
+            },
+            Message::SasStarted(result) => match result {
+                Ok(Some(sas)) => return Action::Task(self.watch_sas(sas)),
+                // The other side started it first
+                Ok(None) => (),
+                Err(error) => self.error = Some(error),
+            },
+            Message::QrGenerated(result) => {
+                if !matches!(self.stage, Stage::Starting) || self.sas.is_some()
+                {
+                    return Action::None;
+                }
+                match result {
+                    // Neither QR code direction works, so go straight to emoji
+                    Ok(None) if !self.can_scan => {
+                        return Action::Task(self.start_sas());
+                    }
+                    Ok(qr) => {
+                        self.stage = Stage::Choose {
+                            qr_code: qr.as_ref().and_then(qr_code),
+                            can_scan: self.can_scan,
+                        };
+                    }
+                    Err(error) => {
+                        self.error = Some(error);
+                        return Action::Task(self.start_sas());
+                    }
+                }
+            }
+            Message::QrChanged(state) => match *state {
+                QrVerificationState::Scanned => self.stage = Stage::Scanned,
+                QrVerificationState::Confirmed
+                | QrVerificationState::Reciprocated => {
+                    self.stage = Stage::Confirmed;
+                }
+                QrVerificationState::Done { .. } => self.stage = Stage::Done,
+                QrVerificationState::Cancelled(info) => self.cancelled(&info),
+                QrVerificationState::Started => (),
+            },
+            Message::CompareEmoji => return Action::Task(self.start_sas()),
+            Message::ScanQrCode => {
+                self.running = true;
+                self.error = None;
+                return Action::Task(Task::perform(
+                    read_qr_code(),
+                    Message::QrCodeRead,
+                ));
+            }
+            Message::QrCodeRead(result) => {
+                self.running = false;
+                match result {
+                    Ok(Some(data)) => {
+                        let Some(request) = self.request.clone() else {
+                            return Action::None;
+                        };
+                        return Action::Task(self.run(async move {
+                            match request.scan_qr_code(*data).await {
+                                Ok(Some(_)) => Ok(()),
+                                Ok(None) => Err(String::from(
+                                    "This verification can't use QR codes \
+                                     anymore",
+                                )),
+                                Err(error) => Err(error.to_string()),
+                            }
+                        }));
+                    }
+                    Ok(None) => (),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::ConfirmScanned => {
+                let Some(qr) = self.qr.clone() else {
+                    return Action::None;
+                };
+                self.stage = Stage::Confirmed;
+                return Action::Task(self.run(async move {
+                    qr.confirm().await.map_err(|error| error.to_string())
+                }));
+            }
+            Message::SasChanged(state) => match *state {
+                SasState::KeysExchanged { emojis, decimals } => {
+                    self.stage = Stage::Compare {
+                        emojis: emojis.map(|emojis| Box::new(emojis.emojis)),
+                        decimals,
+                    };
+                }
+                SasState::Confirmed => self.stage = Stage::Confirmed,
+                SasState::Done { .. } => self.stage = Stage::Done,
+                SasState::Cancelled(info) => self.cancelled(&info),
+                _ => (),
+            },
+            Message::Accept => {
+                let Some(request) = self.request.clone() else {
+                    return Action::None;
+                };
+                self.stage = Stage::Starting;
+                return Action::Task(self.run(async move {
+                    request.accept().await.map_err(|error| error.to_string())
+                }));
+            }
+            Message::Confirm => {
+                let Some(sas) = self.sas.clone() else {
+                    return Action::None;
+                };
+                return Action::Task(self.run(async move {
+                    sas.confirm().await.map_err(|error| error.to_string())
+                }));
+            }
+            Message::Mismatch => {
+                let Some(sas) = self.sas.clone() else {
+                    return Action::None;
+                };
+                return Action::Task(self.run(async move {
+                    sas.mismatch().await.map_err(|error| error.to_string())
+                }));
+            }
+            Message::Cancel => return Action::Task(self.cancel()),
+            Message::Finished(result) => {
+                self.running = false;
+                if let Err(error) = result {
+                    self.error = Some(error);
+                }
+            }
+            Message::TimedOut => {
+                if self.is_active() {
+                    let task = self.cancel();
+                    self.stage = Stage::Cancelled(String::from(
+                        "The verification timed out",
+                    ));
+                    return Action::Task(task);
+                }
+            }
+            Message::Dismiss => {
+                if !self.is_active() {
+                    return Action::Close(Task::none());
+                }
+            }
+            Message::Close => return Action::Close(self.cancel()),
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let title = if self.is_self_verification {
+            String::from("Verify this session")
+        } else {
+            format!("Verify {}", self.name)
+        };
+        let mut content = column![
+            text(title).size(20),
+            text(self.user_id.as_str()).size(FONT_SIZE - 2),
+            rule::horizontal(1),
+        ]
+        .spacing(10)
+        .width(WIDTH);
+
+        let (description, buttons) = match &self.stage {
+            Stage::Requesting => (
+                if self.is_self_verification {
+                    String::from(
+                        "Accept the request on one of your other sessions.",
+                    )
+                } else {
+                    format!("Waiting for {} to accept the request.", self.name)
+                },
+                vec![("Cancel", Message::Cancel, false)],
+            ),
+            Stage::Incoming => (
+                if self.is_self_verification {
+                    String::from(
+                        "One of your other sessions wants to verify this one.",
+                    )
+                } else {
+                    format!("{} wants to verify with you.", self.name)
+                },
+                vec![
+                    ("Decline", Message::Cancel, false),
+                    ("Accept", Message::Accept, true),
+                ],
+            ),
+            Stage::Starting => (
+                String::from("Starting the verification..."),
+                vec![("Cancel", Message::Cancel, false)],
+            ),
+            Stage::Choose { qr_code, can_scan } => {
+                let mut buttons = vec![("Cancel", Message::Cancel, false)];
+                if *can_scan {
+                    buttons.push((
+                        "Scan their code",
+                        Message::ScanQrCode,
+                        false,
+                    ));
+                }
+                buttons.push(("Compare emoji", Message::CompareEmoji, true));
+                let description = match qr_code {
+                    Some(qr_code) => {
+                        content = content.push(center_x(
+                            Image::new(qr_code.clone())
+                                .width(QR_SIZE)
+                                .height(QR_SIZE)
+                                .filter_method(FilterMethod::Nearest),
+                        ));
+                        "Scan this code with the other device, or pick \
+                         another way to verify."
+                    }
+                    None if *can_scan => {
+                        "Take a picture of the code on the other device and \
+                         pick it, or compare emoji instead."
+                    }
+                    None => "Compare emoji with the other device.",
+                };
+                (String::from(description), buttons)
+            }
+            Stage::Scanned => (
+                String::from(
+                    "Does the other device say it scanned the code \
+                     successfully?",
+                ),
+                vec![
+                    ("No", Message::Cancel, false),
+                    ("Yes", Message::ConfirmScanned, true),
+                ],
+            ),
+            Stage::Compare { emojis, decimals } => {
+                content = content.push(match emojis {
+                    Some(emojis) => view_emojis(emojis),
+                    None => {
+                        let (first, second, third) = decimals;
+                        text(format!("{first} {second} {third}"))
+                            .size(24)
+                            .into()
+                    }
+                });
+                (
+                    String::from(
+                        "Check that the other side shows the same, in the same \
+                         order.",
+                    ),
+                    vec![
+                        ("They don't match", Message::Mismatch, false),
+                        ("They match", Message::Confirm, true),
+                    ],
+                )
+            }
+            Stage::Confirmed => (
+                String::from("Waiting for the other side to confirm..."),
+                vec![("Cancel", Message::Cancel, false)],
+            ),
+            Stage::Done => (
+                if self.is_self_verification {
+                    String::from("This session is verified.")
+                } else {
+                    format!("{} is verified.", self.name)
+                },
+                vec![("Close", Message::Close, true)],
+            ),
+            Stage::Cancelled(reason) => {
+                (reason.clone(), vec![("Close", Message::Close, false)])
+            }
+        };
+        content = content.push(text(description).size(FONT_SIZE));
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+
+        let mut actions = row![text("").width(Length::Fill)]
+            .spacing(10)
+            .align_y(Alignment::Center);
+        if self.running
+            || matches!(self.stage, Stage::Requesting | Stage::Confirmed)
+        {
+            actions = actions.push(
+                Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+            );
+        }
+        for (label, message, is_primary) in buttons {
+            actions = actions.push(
+                button(text(label).size(FONT_SIZE))
+                    .style(if is_primary {
+                        button::primary
+                    } else {
+                        button::secondary
+                    })
+                    .on_press_maybe((!self.running).then_some(message)),
+            );
+        }
+
+        content.push(actions).into()
+    }
+
+    fn watch_request(&mut self, request: VerificationRequest) -> Task<Message> {
+        let state = request.state();
+        let (updates, handle) = Task::run(request.changes(), |state| {
+            Message::RequestChanged(Box::new(state))
+        })
+        .abortable();
+        self.request = Some(request);
+        self._request_updates = Some(handle.abort_on_drop());
+
+        // The request may have moved on before we started listening
+        match self.update(Message::RequestChanged(Box::new(state))) {
+            Action::Task(task) => Task::batch([updates, task]),
+            _ => updates,
+        }
+    }
+
+    fn watch_sas(&mut self, sas: SasVerification) -> Task<Message> {
+        if self.sas.is_some() {
+            return Task::none();
+        }
+        let state = sas.state();
+        let (updates, handle) = Task::run(sas.changes(), |state| {
+            Message::SasChanged(Box::new(state))
+        })
+        .abortable();
+        self._sas_updates = Some(handle.abort_on_drop());
+        self.stage = Stage::Starting;
+        self.sas = Some(sas.clone());
+        // Switching to emoji abandons the QR code
+        self.qr = None;
+        self._qr_updates = None;
+        self.update(Message::SasChanged(Box::new(state)));
+
+        // Whoever didn't start the SAS flow accepts it
+        if sas.we_started() {
+            return updates;
+        }
+        let accept = self.run(async move {
+            sas.accept().await.map_err(|error| error.to_string())
+        });
+        Task::batch([updates, accept])
+    }
+
+    fn watch_qr(&mut self, qr: QrVerification) -> Task<Message> {
+        if self.sas.is_some() {
+            return Task::none();
+        }
+        // Scanning their code replaces the one we were showing
+        let state = qr.state();
+        let (updates, handle) = Task::run(qr.changes(), |state| {
+            Message::QrChanged(Box::new(state))
+        })
+        .abortable();
+        self._qr_updates = Some(handle.abort_on_drop());
+        self.qr = Some(qr);
+        self.update(Message::QrChanged(Box::new(state)));
+
+        updates
+    }
+
+    fn start_sas(&mut self) -> Task<Message> {
+        let Some(request) = self.request.clone() else {
+            return Task::none();
+        };
+        self.stage = Stage::Starting;
+        Task::perform(
+            async move {
+                request.start_sas().await.map_err(|error| error.to_string())
+            },
+            Message::SasStarted,
+        )
+    }
+
+    fn run(
+        &mut self,
+        future: impl Future<Output = Result<(), String>> + Send + 'static,
+    ) -> Task<Message> {
+        self.running = true;
+        self.error = None;
+        Task::perform(future, Message::Finished)
+    }
+
+    /// Cancels the verification if it's still going on.
+    fn cancel(&mut self) -> Task<Message> {
+        if !self.is_active() {
+            return Task::none();
+        }
+        if let Some(sas) = self.sas.clone() {
+            self.run(async move {
+                sas.cancel().await.map_err(|error| error.to_string())
+            })
+        } else if let Some(qr) = self.qr.clone() {
+            self.run(async move {
+                qr.cancel().await.map_err(|error| error.to_string())
+            })
+        } else if let Some(request) = self.request.clone() {
+            self.run(async move {
+                request.cancel().await.map_err(|error| error.to_string())
+            })
+        } else {
+            Task::none()
+        }
+    }
+
+    fn cancelled(&mut self, info: &CancelInfo) {
+        let reason = if *info.cancel_code() == CancelCode::Timeout {
+            String::from("The verification timed out")
+        } else if *info.cancel_code() == CancelCode::MismatchedSas {
+            String::from(
+                "The emoji didn't match, so the verification was cancelled",
+            )
+        } else if info.cancelled_by_us() {
+            String::from("The verification was cancelled")
+        } else {
+            format!("The other side cancelled: {}", info.reason())
+        };
+        self.stage = Stage::Cancelled(reason);
+    }
+
+    fn is_active(&self) -> bool {
+        !matches!(self.stage, Stage::Done | Stage::Cancelled(_))
+    }
+}
+
+fn view_emojis(emojis: &[Emoji; 7]) -> Element<'_, Message> {
+    Row::with_children(emojis.iter().map(|emoji| {
+        column![
+            text(emoji.symbol).size(EMOJI_SIZE),
+            text(emoji.description).size(FONT_SIZE - 2)
+        ]
+        .spacing(4)
+        .align_x(Alignment::Center)
+        .width(Length::Fill)
+        .into()
+    }))
+    .spacing(4)
+    .into()
+}
+
+/// Renders the QR code of a verification as black and white pixels.
+fn qr_code(qr: &QrVerification) -> Option<Handle> {
+    let code = qr.to_qr_code().ok()?;
+    let width = code.width();
+    let colors = code.to_colors();
+    let size = width + 2 * QR_QUIET_ZONE;
+    let mut pixels = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let value = x
+                .checked_sub(QR_QUIET_ZONE)
+                .zip(y.checked_sub(QR_QUIET_ZONE))
+                .filter(|&(x, y)| x < width && y < width)
+                .map_or(255, |(x, y)| colors[y * width + x].select(0, 255));
+            pixels.extend([value, value, value, 255]);
+        }
+    }
+    Some(Handle::from_rgba(size as u32, size as u32, pixels))
+}
+
+/// Asks for a picture of the other device's QR code and reads it. `None` if
+/// the user closed the dialog.
+async fn read_qr_code() -> Result<Option<Box<QrVerificationData>>, String> {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .add_filter("Images", &["png", "jpg", "jpeg", "webp", "gif"])
+        .pick_file()
+        .await
+    else {
+        return Ok(None);
+    };
+    let path = file.path().to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(&path).map_err(|error| error.to_string())?;
+        let image = image::load_from_memory(&bytes)
+            .map_err(|error| error.to_string())?
+            .to_luma8();
+        let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+            image.width() as usize,
+            image.height() as usize,
+            |x, y| image.get_pixel(x as u32, y as u32).0[0],
+        );
+        prepared
+            .detect_grids()
+            .into_iter()
+            .find_map(|grid| {
+                let mut bytes = Vec::new();
+                grid.decode_to(&mut bytes).ok()?;
+                QrVerificationData::from_bytes(bytes).ok()
+            })
+            .map(|data| Some(Box::new(data)))
+            .ok_or_else(|| {
+                String::from("There's no verification QR code in that picture")
+            })
+    })
+    .await
+    .map_err(|error| error.to_string())?
+}
+
+fn timeout() -> Task<Message> {
+    Task::perform(sleep(TIMEOUT), |()| Message::TimedOut)
+}
+
+async fn request_verification(
+    client: Client,
+    user_id: OwnedUserId,
+) -> Result<VerificationRequest, String> {
+    let identity = client
+        .encryption()
+        .request_user_identity(&user_id)
+        .await
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| {
+            String::from("There are no cross-signing keys to verify against")
+        })?;
+    identity
+        .request_verification()
+        .await
+        .map_err(|error| error.to_string())
+}
+
+/// Verification requests sent to us, either to this device or in a DM.
+pub fn requests(client: Client) -> impl Stream<Item = VerificationRequest> {
+    iced::stream::channel(4, async move |output| {
+        let to_device = client.add_event_handler({
+            let output = output.clone();
+            move |event: ToDeviceKeyVerificationRequestEvent, client: Client| {
+                let mut output = output.clone();
+                async move {
+                    if let Some(request) = client
+                        .encryption()
+                        .get_verification_request(
+                            &event.sender,
+                            &event.content.transaction_id,
+                        )
+                        .await
+                    {
+                        let _ = output.send(request).await;
+                    }
+                }
+            }
+        });
+        let in_room = client.add_event_handler(
+            move |event: OriginalSyncRoomMessageEvent, client: Client| {
+                let mut output = output.clone();
+                async move {
+                    let MessageType::VerificationRequest(_) =
+                        event.content.msgtype
+                    else {
+                        return;
+                    };
+                    if let Some(request) = client
+                        .encryption()
+                        .get_verification_request(
+                            &event.sender,
+                            &event.event_id,
+                        )
+                        .await
+                        && !request.we_started()
+                    {
+                        let _ = output.send(request).await;
+                    }
+                }
+            },
+        );
+        let _guards = (
+            client.event_handler_drop_guard(to_device),
+            client.event_handler_drop_guard(in_room),
+        );
+
+        // Listen for as long as the chat screen is open
+        future::pending::<()>().await;
+    })
+}
@@ -1,8 +1,7 @@
 use crate::APP_NAME;
 use crate::loading_spinner::Spinner;
+use crate::modal;
 use iced::Alignment;
-use iced::Background;
-use iced::Color;
 use iced::Element;
 use iced::Length;
 use iced::Task;
@@ -42,6 +41,8 @@ pub struct App {
     password_visible: bool,
     homeserver_state: HomeserverState,
     client: Option<Client>,
+    logging_in: bool,
+    login_error: Option<String>,
 }
 
 #[derive(Clone)]
@@ -96,6 +97,8 @@ impl App {
             password_visible: false,
             homeserver_state: HomeserverState::default(),
             client: None,
+            logging_in: false,
+            login_error: None,
         }
     }
 
@@ -142,12 +145,39 @@ impl App {
                     self.homeserver_state = HomeserverState::Error(error)
                 }
             },
-            Message::InitiatePasswordLogin => (),
+            Message::InitiatePasswordLogin => {
+                let Some(client) = self.client.clone() else {
+                    return Action::None;
+                };
+                if self.logging_in || self.username.trim().is_empty() {
+                    return Action::None;
+                }
+                self.logging_in = true;
+                self.login_error = None;
+                return Action::Task(Task::perform(
+                    login_with_password(
+                        client,
+                        self.username.trim().to_string(),
+                        self.password.clone(),
+                    ),
+                    Message::LoginStatus,
+                ));
+            }
             Message::InitiateSsoLogin => (),
-            Message::LoginStatus(result) => (),
+            Message::LoginStatus(result) => {
+                self.logging_in = false;
+                match (result, self.client.clone()) {
+                    (Ok(()), Some(client)) => {
+                        self.password.clear();
+                        return Action::LoggedIn(client);
+                    }
+                    (Ok(()), None) => (),
+                    (Err(error), _) => self.login_error = Some(error),
+                }
+            }
         }
 
-        Action::Task(Task::none())
+        Action::None
     }
 
     pub fn view(&self) -> Element<'_, Message> {
@@ -245,6 +275,7 @@ impl App {
                                 .width(LABEL_WIDTH),
                             text_input("Password", &self.password)
                                 .on_input(Message::PasswordInput)
+                                .on_submit(Message::InitiatePasswordLogin)
                                 .size(FONT_SIZE)
                                 .secure(!self.password_visible)
                                 .width(TEXTBOX_WIDTH),
@@ -262,13 +293,24 @@ impl App {
                         .into(),
                     );
 
-                    items.push(
-                        center_x(
-                            button(text("Login").size(FONT_SIZE))
-                                .on_press(Message::InitiatePasswordLogin),
+                    let mut login = row![
+                        button(text("Login").size(FONT_SIZE)).on_press_maybe(
+                            (!self.logging_in)
+                                .then_some(Message::InitiatePasswordLogin)
                         )
-                        .into(),
-                    );
+                    ]
+                    .spacing(10)
+                    .align_y(Alignment::Center);
+                    if self.logging_in {
+                        login = login.push(
+                            Spinner::new()
+                                .cycle_duration(Duration::from_secs_f32(1.0)),
+                        );
+                    }
+                    items.push(center_x(login).into());
+                    if let Some(error) = &self.login_error {
+                        items.push(text(error).size(FONT_SIZE).into());
+                    }
                     if auth_types.sso {
                         items.push(rule::horizontal(1).into());
                     }
@@ -298,32 +340,9 @@ impl App {
             container(scrollable(content))
                 .width(Length::Shrink)
                 .height(Length::Shrink)
-                .style(|theme| {
-                    let palette = theme.extended_palette();
-                    container::Style {
-                        background: Some(Background::Color(
-                            palette.background.base.color,
-                        )),
-                        border: iced::Border {
-                            radius: 5.0.into(),
-                            width: 1.0,
-                            color: palette.background.strong.color,
-                        },
-                        shadow: iced::Shadow {
-                            color: Color::from_rgba(0.0, 0.0, 0.0, 0.25),
-                            blur_radius: 0.0,
-                            offset: iced::Vector { x: 10.0, y: 10.0 },
-                        },
-                        ..Default::default()
-                    }
-                }),
+                .style(modal::style),
         ))))
-        .style(|_theme| container::Style {
-            background: Some(Background::Color(Color::from_linear_rgba(
-                0.0, 0.0, 0.0, 0.2,
-            ))),
-            ..Default::default()
-        })
+        .style(modal::backdrop)
         .into()
     }
 }
@@ -370,8 +389,8 @@ async fn get_auth_types(client: Client) -> Result<AuthTypes, String> {
 
 async fn login_with_password(
     client: Client,
-    username: &str,
-    password: &str,
+    username: String,
+    password: String,
 ) -> Result<(), String> {
     match client
         .matrix_auth()
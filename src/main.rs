@@ -1,8 +1,12 @@
 mod chat;
 mod loading_spinner;
 mod login;
+mod modal;
 mod restore;
+mod typing_indicator;
+mod verify_session;
 
+use iced::Subscription;
 use iced::Task;
 use iced::Theme;
 use iced::window;
@@ -12,13 +16,15 @@ pub const APP_NAME: &str = "Iced Matrix Client";
 enum Screen {
     Restore(restore::App),
     Login(login::App),
-    Chat(chat::App),
+    VerifySession(Box<verify_session::App>),
+    Chat(Box<chat::App>),
 }
 
 #[derive(Clone)]
 enum Message {
     Restore(restore::Message),
     Login(login::Message),
+    VerifySession(verify_session::Message),
     Chat(chat::Message),
 }
 
@@ -37,10 +43,20 @@ impl App {
         match &self.screen {
             Screen::Restore(restore) => restore.view().map(Message::Restore),
             Screen::Login(login) => login.view().map(Message::Login),
+            Screen::VerifySession(verify_session) => {
+                verify_session.view().map(Message::VerifySession)
+            }
             Screen::Chat(chat) => chat.view().map(Message::Chat),
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        match &self.screen {
+            Screen::Chat(chat) => chat.subscription().map(Message::Chat),
+            _ => Subscription::none(),
+        }
+    }
+
     fn update(&mut self, message: Message) -> iced::Task<Message> {
         match (&mut self.screen, message) {
             (Screen::Restore(restore), Message::Restore(msg)) => {
@@ -59,10 +75,28 @@ impl App {
                         return task.map(Message::Login);
                     }
                     login::Action::LoggedIn(client) => {
-                        todo!();
+                        let (verify_session, task) =
+                            verify_session::App::new(client);
+                        self.screen =
+                            Screen::VerifySession(Box::new(verify_session));
+                        return task.map(Message::VerifySession);
                     }
                 }
             }
+            (
+                Screen::VerifySession(verify_session),
+                Message::VerifySession(msg),
+            ) => match verify_session.update(msg) {
+                verify_session::Action::None => (),
+                verify_session::Action::Task(task) => {
+                    return task.map(Message::VerifySession);
+                }
+                verify_session::Action::Done(client) => {
+                    let (chat, task) = chat::App::new(client);
+                    self.screen = Screen::Chat(Box::new(chat));
+                    return task.map(Message::Chat);
+                }
+            },
             (Screen::Chat(chat), Message::Chat(msg)) => {
                 let action = chat.update(msg);
                 match action {
@@ -82,6 +116,7 @@ impl App {
 fn main() -> iced::Result {
     iced::application(App::new, App::update, App::view)
         .title(APP_NAME)
+        .subscription(App::subscription)
         .window(window::Settings {
             maximized: true,
             ..Default::default()
@@ -0,0 +1,58 @@
+// Dialogs drawn on top of a screen, and the box and backdrop styles every
+// dialog shares
+use iced::Background;
+use iced::Color;
+use iced::Element;
+use iced::Theme;
+use iced::widget::center;
+use iced::widget::container;
+use iced::widget::mouse_area;
+use iced::widget::opaque;
+use iced::widget::stack;
+
+/// Shows `content` in a box above `base`. Clicking outside the box produces
+/// `on_blur`.
+pub fn modal<'a, Message: Clone + 'a>(
+    base: impl Into<Element<'a, Message>>,
+    content: impl Into<Element<'a, Message>>,
+    on_blur: Message,
+) -> Element<'a, Message> {
+    stack![
+        base.into(),
+        opaque(
+            mouse_area(
+                center(opaque(container(content).padding(10).style(style)))
+                    .style(backdrop)
+            )
+            .on_press(on_blur)
+        )
+    ]
+    .into()
+}
+
+pub fn style(theme: &Theme) -> container::Style {
+    let palette = theme.extended_palette();
+    container::Style {
+        background: Some(Background::Color(palette.background.base.color)),
+        border: iced::Border {
+            radius: 5.0.into(),
+            width: 1.0,
+            color: palette.background.strong.color,
+        },
+        shadow: iced::Shadow {
+            color: Color::from_rgba(0.0, 0.0, 0.0, 0.25),
+            blur_radius: 0.0,
+            offset: iced::Vector { x: 10.0, y: 10.0 },
+        },
+        ..Default::default()
+    }
+}
+
+pub fn backdrop(_theme: &Theme) -> container::Style {
+    container::Style {
+        background: Some(Background::Color(Color::from_linear_rgba(
+            0.0, 0.0, 0.0, 0.2,
+        ))),
+        ..Default::default()
+    }
+}
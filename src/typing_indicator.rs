@@ -0,0 +1,203 @@
+// Shows three dots bouncing one after another, like a chat typing indicator
+// Built the same way as the loading spinner and shares its style sheet
+use crate::loading_spinner::StyleSheet;
+use iced::advanced::layout;
+use iced::advanced::renderer;
+use iced::advanced::widget::tree::{self, Tree};
+use iced::advanced::{self, Clipboard, Layout, Shell, Widget};
+use iced::mouse;
+use iced::time::Instant;
+use iced::widget::canvas;
+use iced::window;
+use iced::{Element, Event, Length, Point, Rectangle, Renderer, Size, Vector};
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+const DOTS: usize = 3;
+// How far behind the previous dot each dot starts, as a fraction of a cycle
+const DOT_DELAY: f32 = 0.15;
+
+pub struct TypingIndicator<Theme>
+where
+    Theme: StyleSheet,
+{
+    dot_size: f32,
+    style: <Theme as StyleSheet>::Style,
+    cycle_duration: Duration,
+}
+
+impl<Theme> TypingIndicator<Theme>
+where
+    Theme: StyleSheet,
+{
+    /// Creates a new [`TypingIndicator`].
+    pub fn new() -> Self {
+        TypingIndicator {
+            dot_size: 6.0,
+            style: <Theme as StyleSheet>::Style::default(),
+            cycle_duration: Duration::from_millis(1200),
+        }
+    }
+
+    /// Sets the diameter of each dot of the [`TypingIndicator`].
+    pub fn dot_size(mut self, dot_size: f32) -> Self {
+        self.dot_size = dot_size;
+        self
+    }
+
+    /// Sets how long it takes for all dots of the [`TypingIndicator`] to
+    /// bounce once.
+    pub fn cycle_duration(mut self, duration: Duration) -> Self {
+        self.cycle_duration = duration;
+        self
+    }
+
+    fn width(&self) -> f32 {
+        // Dots are spaced by half their size
+        self.dot_size * (DOTS as f32 * 1.5 - 0.5)
+    }
+
+    fn height(&self) -> f32 {
+        // Leaves room for a dot to bounce up by its own size
+        self.dot_size * 2.0
+    }
+}
+
+impl<Theme> Default for TypingIndicator<Theme>
+where
+    Theme: StyleSheet,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct State {
+    start: Instant,
+    now: Instant,
+    cache: canvas::Cache,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            now,
+            cache: canvas::Cache::default(),
+        }
+    }
+}
+
+impl<Message, Theme> Widget<Message, Theme, Renderer> for TypingIndicator<Theme>
+where
+    Message: Clone,
+    Theme: StyleSheet,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size {
+            width: Length::Fixed(self.width()),
+            height: Length::Fixed(self.height()),
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _tree: &mut Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::atomic(limits, self.width(), self.height())
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        _layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        _viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_mut::<State>();
+
+        if let Event::Window(window::Event::RedrawRequested(now)) = event {
+            state.now = *now;
+            state.cache.clear();
+            shell.request_redraw();
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        use advanced::Renderer as _;
+
+        let state = tree.state.downcast_ref::<State>();
+        let bounds = layout.bounds();
+        let custom_style =
+            <Theme as StyleSheet>::appearance(theme, &self.style);
+
+        let elapsed = state.now.saturating_duration_since(state.start);
+        let progress =
+            (elapsed.as_secs_f32() / self.cycle_duration.as_secs_f32()).fract();
+
+        let geometry = state.cache.draw(renderer, bounds.size(), |frame| {
+            let radius = self.dot_size / 2.0;
+
+            for dot in 0..DOTS {
+                let phase = (progress - dot as f32 * DOT_DELAY).rem_euclid(1.0);
+                // Each dot bounces during the first half of its phase and
+                // rests during the second
+                let lift = (phase * 2.0 * PI).sin().max(0.0);
+
+                let center = Point::new(
+                    radius + dot as f32 * self.dot_size * 1.5,
+                    frame.height() - radius - lift * self.dot_size,
+                );
+                let mut color = custom_style.bar_color;
+                color.a *= 0.4 + 0.6 * lift;
+
+                frame.fill(&canvas::Path::circle(center, radius), color);
+            }
+        });
+
+        renderer.with_translation(
+            Vector::new(bounds.x, bounds.y),
+            |renderer| {
+                use iced::advanced::graphics::geometry::Renderer as _;
+
+                renderer.draw_geometry(geometry);
+            },
+        );
+    }
+}
+
+impl<'a, Message, Theme> From<TypingIndicator<Theme>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: Clone + 'a,
+    Theme: StyleSheet + 'a,
+{
+    fn from(indicator: TypingIndicator<Theme>) -> Self {
+        Self::new(indicator)
+    }
+}
@@ -0,0 +1,352 @@
+// Shown between login and chat when the account already has cross-signing,
+// so the new session can be verified with another one or the recovery key
+use crate::chat;
+use crate::chat::verification;
+use crate::chat::verification::Verification;
+use crate::loading_spinner::Spinner;
+use crate::modal;
+use crate::modal::modal;
+use iced::Alignment;
+use iced::Element;
+use iced::Length;
+use iced::Task;
+use iced::task;
+use iced::widget::button;
+use iced::widget::center;
+use iced::widget::center_x;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::row;
+use iced::widget::rule;
+use iced::widget::text;
+use iced::widget::text_input;
+use matrix_sdk::Client;
+use matrix_sdk::encryption::VerificationState;
+use matrix_sdk::encryption::verification::VerificationRequest;
+use std::time::Duration;
+
+const FONT_SIZE: u32 = 13;
+const WIDTH: f32 = 400.0;
+
+pub enum Action {
+    None,
+    Task(Task<Message>),
+    Done(Client),
+}
+
+enum Stage {
+    // Finding out whether the account has cross-signing
+    Checking,
+    Choose,
+    RecoveryKey,
+}
+
+pub struct App {
+    client: Client,
+    stage: Stage,
+    verification: Option<Verification>,
+    recovery_key: String,
+    recovering: bool,
+    error: Option<String>,
+    // Kept apart so a sync hiccup doesn't hide or clear a recovery error
+    sync_error: Option<String>,
+    // Syncing delivers the other session's verification messages until the
+    // chat screen takes over
+    _updates: task::Handle,
+}
+
+#[derive(Clone)]
+pub enum Message {
+    Checked(bool),
+    StateChanged(VerificationState),
+    Synced(Result<(), String>),
+    VerifyWithSession,
+    VerificationRequested(VerificationRequest),
+    Verification(verification::Message),
+    UseRecoveryKey,
+    RecoveryKeyInput(String),
+    Recover,
+    Recovered(Result<(), String>),
+    Back,
+    Skip,
+}
+
+impl App {
+    pub fn new(client: Client) -> (Self, Task<Message>) {
+        let (updates, handle) = Task::batch([
+            Task::run(
+                client.encryption().verification_state(),
+                Message::StateChanged,
+            ),
+            Task::run(chat::sync(client.clone()), Message::Synced),
+            Task::run(
+                verification::requests(client.clone()),
+                Message::VerificationRequested,
+            ),
+        ])
+        .abortable();
+        let check = Task::perform(is_needed(client.clone()), Message::Checked);
+
+        (
+            Self {
+                client,
+                stage: Stage::Checking,
+                verification: None,
+                recovery_key: String::new(),
+                recovering: false,
+                error: None,
+                sync_error: None,
+                _updates: handle.abort_on_drop(),
+            },
+            Task::batch([updates, check]),
+        )
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Checked(needed) => {
+                if !needed {
+                    return Action::Done(self.client.clone());
+                }
+                if let Stage::Checking = self.stage {
+                    self.stage = Stage::Choose;
+                }
+            }
+            Message::StateChanged(state) => {
+                if state == VerificationState::Verified {
+                    return Action::Done(self.client.clone());
+                }
+            }
+            Message::Synced(result) => self.sync_error = result.err(),
+            Message::VerifyWithSession => {
+                let Some(user_id) = self.client.user_id() else {
+                    return Action::None;
+                };
+                let (verification, task) = Verification::outgoing(
+                    self.client.clone(),
+                    user_id.to_owned(),
+                    String::from("your other sessions"),
+                );
+                self.verification = Some(verification);
+                return Action::Task(task.map(Message::Verification));
+            }
+            // Another session may start the verification, as it would once
+            // we're in the chat screen
+            Message::VerificationRequested(request) => {
+                if self.verification.is_some() {
+                    return Action::None;
+                }
+                let (verification, task) = Verification::incoming(request);
+                self.verification = Some(verification);
+                return Action::Task(task.map(Message::Verification));
+            }
+            Message::Verification(msg) => {
+                let Some(verification) = &mut self.verification else {
+                    return Action::None;
+                };
+                match verification.update(msg) {
+                    verification::Action::None => (),
+                    verification::Action::Task(task) => {
+                        return Action::Task(task.map(Message::Verification));
+                    }
+                    verification::Action::Close(task) => {
+                        self.verification = None;
+                        return Action::Task(task.map(Message::Verification));
+                    }
+                }
+            }
+            Message::UseRecoveryKey => {
+                self.stage = Stage::RecoveryKey;
+                self.error = None;
+            }
+            Message::RecoveryKeyInput(recovery_key) => {
+                self.recovery_key = recovery_key;
+            }
+            Message::Recover => {
+                if self.recovering || self.recovery_key.trim().is_empty() {
+                    return Action::None;
+                }
+                self.recovering = true;
+                self.error = None;
+                let client = self.client.clone();
+                let recovery_key = self.recovery_key.trim().to_string();
+                return Action::Task(Task::perform(
+                    async move {
+                        client
+                            .encryption()
+                            .recovery()
+                            .recover(&recovery_key)
+                            .await
+                            .map_err(|error| error.to_string())
+                    },
+                    Message::Recovered,
+                ));
+            }
+            Message::Recovered(result) => {
+                self.recovering = false;
+                match result {
+                    Ok(()) => return Action::Done(self.client.clone()),
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            Message::Back => {
+                if !self.recovering {
+                    self.stage = Stage::Choose;
+                    self.error = None;
+                }
+            }
+            Message::Skip => return Action::Done(self.client.clone()),
+        }
+
+        Action::None
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut content = column![
+            center_x(text("Verify this session").size(20)),
+            rule::horizontal(1)
+        ]
+        .spacing(15)
+        .width(WIDTH);
+
+        match &self.stage {
+            Stage::Checking => {
+                content = content.push(center_x(
+                    Spinner::new().cycle_duration(Duration::from_secs_f32(1.0)),
+                ));
+            }
+            Stage::Choose => {
+                content = content
+                    .push(
+                        text(
+                            "Confirm it's you to read your encrypted message \
+                             history and show others this session is yours.",
+                        )
+                        .size(FONT_SIZE),
+                    )
+                    .push(
+                        column![
+                            button(center_x(
+                                text("Verify with another session")
+                                    .size(FONT_SIZE)
+                            ))
+                            .width(Length::Fill)
+                            .on_press(Message::VerifyWithSession),
+                            button(center_x(
+                                text("Use recovery key").size(FONT_SIZE)
+                            ))
+                            .width(Length::Fill)
+                            .style(button::secondary)
+                            .on_press(Message::UseRecoveryKey),
+                        ]
+                        .spacing(10),
+                    )
+                    .push(rule::horizontal(1))
+                    .push(
+                        text(
+                            "If you skip, this session stays unverified: old \
+                             messages can't be decrypted and others may not \
+                             trust it.",
+                        )
+                        .size(FONT_SIZE - 1),
+                    )
+                    .push(
+                        row![
+                            text("").width(Length::Fill),
+                            button(text("Skip").size(FONT_SIZE))
+                                .style(button::text)
+                                .on_press(Message::Skip)
+                        ]
+                        .align_y(Alignment::Center),
+                    );
+            }
+            Stage::RecoveryKey => {
+                let mut buttons = row![text("").width(Length::Fill)]
+                    .spacing(10)
+                    .align_y(Alignment::Center);
+                if self.recovering {
+                    buttons = buttons.push(
+                        Spinner::new()
+                            .cycle_duration(Duration::from_secs_f32(1.0)),
+                    );
+                }
+                buttons = buttons
+                    .push(
+                        button(text("Back").size(FONT_SIZE))
+                            .style(button::secondary)
+                            .on_press_maybe(
+                                (!self.recovering).then_some(Message::Back),
+                            ),
+                    )
+                    .push(
+                        button(text("Continue").size(FONT_SIZE))
+                            .on_press_maybe(
+                                (!self.recovering
+                                    && !self.recovery_key.trim().is_empty())
+                                .then_some(Message::Recover),
+                            ),
+                    );
+                content = content
+                    .push(
+                        text(
+                            "Enter the recovery key or passphrase you saved \
+                             when setting up secure messaging.",
+                        )
+                        .size(FONT_SIZE),
+                    )
+                    .push(
+                        text_input(
+                            "Recovery key or passphrase",
+                            &self.recovery_key,
+                        )
+                        .secure(true)
+                        .on_input(Message::RecoveryKeyInput)
+                        .on_submit(Message::Recover)
+                        .size(FONT_SIZE),
+                    )
+                    .push(buttons);
+            }
+        }
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error).size(FONT_SIZE));
+        }
+        if let Some(error) = &self.sync_error {
+            content = content.push(
+                text(format!("Couldn't reach the server: {error}"))
+                    .size(FONT_SIZE - 1),
+            );
+        }
+
+        let screen = center(container(content).padding(10).style(modal::style))
+            .style(modal::backdrop);
+
+        match &self.verification {
+            Some(verification) => modal(
+                screen,
+                verification.view().map(Message::Verification),
+                Message::Verification(verification::Message::Dismiss),
+            ),
+            None => screen.into(),
+        }
+    }
+}
+
+/// Whether the account has a cross-signing identity this session isn't
+/// verified with yet.
+async fn is_needed(client: Client) -> bool {
+    let Some(user_id) = client.user_id().map(ToOwned::to_owned) else {
+        return false;
+    };
+    let encryption = client.encryption();
+    if !matches!(
+        encryption.request_user_identity(&user_id).await,
+        Ok(Some(_))
+    ) {
+        return false;
+    }
+    !matches!(
+        encryption.get_own_device().await,
+        Ok(Some(device)) if device.is_verified()
+    )
+}